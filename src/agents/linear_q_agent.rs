@@ -0,0 +1,272 @@
+//
+// Linear Q-learning agent
+//
+// It estimates the value of playing each valid card as a linear function of hand-crafted features,
+// and plays the card with the highest value (or a random valid card with probability epsilon while training).
+// The value is the negative number of penalty points (/ the total of the rule set) that the agent will acquire
+// from the current trick to the end of the game; see train.rs for the training loop.
+//
+// The weights are saved as a text file with one weight per line, in the order of the features below.
+//
+
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::clock::TimeBudget;
+use crate::error::HeartsError;
+use crate::rules::RuleSet;
+use crate::{
+    check_hand, get_penalty_point, get_suit, is_valid_card, Agent, NUM_CARDS, NUM_KC, S_A, S_K, S_Q,
+};
+
+// Total number of penalty points in a game under the rule set (26 by default),
+// used to normalize the features and rewards.
+pub fn get_penalty_scale(rules: &RuleSet) -> f32 {
+    return rules.get_total_penalty_points().max(1) as f32;
+}
+
+// Largest number of penalty points of a card under the rule set (13 for S-Q by default).
+fn get_card_penalty_scale(rules: &RuleSet) -> f32 {
+    let max = rules
+        .get_deck()
+        .iter()
+        .map(|c| get_penalty_point(*c, rules).abs())
+        .max()
+        .unwrap_or(0);
+    return max.max(1) as f32;
+}
+
+// Features:
+//   0      bias
+//   1-4    number of clubs, diamonds, spades and hearts in the hand (/ NUM_KC)
+//   5-7    S-Q, S-K and S-A are in the hand
+//   8      S-Q has already been played
+//   9      breaking heart has occurred
+//   10     penalty points in the current trick (/ total penalty points)
+//   11-14  suit of the card
+//   15     rank of the card (2 -> 0, A -> 1)
+//   16     penalty points of the card (/ the largest penalty points of a card)
+//   17     the card is the leading card of the trick
+//   18     the card does not follow the suit of the leading card
+//   19     the card wins the trick so far
+//   20     the card wins the trick so far, multiplied by the penalty points in the trick including the card
+//          (/ total penalty points)
+// The totals are those of the rule set, so that the features stay in the same range under any scoring.
+//   21     the card is a spade higher than S-Q while S-Q has not been played
+pub const NUM_FEATURES: usize = 22;
+
+pub fn calc_features(
+    hand: &[i32],
+    whole_card_sequence: &[i32; NUM_CARDS],
+    trick: usize,
+    turn: usize,
+    bh_flag: bool,
+    card: i32,
+    rules: &RuleSet,
+) -> [f32; NUM_FEATURES] {
+    let mut features: [f32; NUM_FEATURES] = [0.0; NUM_FEATURES];
+    let penalty_scale = get_penalty_scale(rules);
+    let idx = trick * rules.num_players + turn;
+    let card_sequence = &whole_card_sequence[(trick * rules.num_players)..idx];
+
+    features[0] = 1.0;
+
+    for h in hand {
+        if *h != -1 {
+            features[1 + get_suit(*h) as usize] += 1.0 / (NUM_KC as f32);
+        }
+        if *h == S_Q {
+            features[5] = 1.0;
+        } else if *h == S_K {
+            features[6] = 1.0;
+        } else if *h == S_A {
+            features[7] = 1.0;
+        }
+    }
+
+    let sq_played = whole_card_sequence[..idx].contains(&S_Q);
+    if sq_played {
+        features[8] = 1.0;
+    }
+    if bh_flag {
+        features[9] = 1.0;
+    }
+
+    let trick_penalty: i32 = card_sequence
+        .iter()
+        .map(|c| get_penalty_point(*c, rules))
+        .sum();
+    features[10] = (trick_penalty as f32) / penalty_scale;
+
+    features[11 + get_suit(card) as usize] = 1.0;
+    features[15] = ((card % (NUM_KC as i32)) as f32) / ((NUM_KC - 1) as f32);
+    features[16] = (get_penalty_point(card, rules) as f32) / get_card_penalty_scale(rules);
+
+    let wins = if turn == 0 {
+        features[17] = 1.0;
+        true
+    } else {
+        let leading_card = card_sequence[0];
+        if get_suit(card) != get_suit(leading_card) {
+            features[18] = 1.0;
+            false
+        } else {
+            card_sequence
+                .iter()
+                .all(|c| get_suit(*c) != get_suit(card) || *c < card)
+        }
+    };
+    if wins {
+        features[19] = 1.0;
+        features[20] = ((trick_penalty + get_penalty_point(card, rules)) as f32) / penalty_scale;
+    }
+
+    if (card == S_K || card == S_A) && !sq_played {
+        features[21] = 1.0;
+    }
+
+    return features;
+}
+
+pub fn calc_value(weights: &[f32; NUM_FEATURES], features: &[f32; NUM_FEATURES]) -> f32 {
+    return weights
+        .iter()
+        .zip(features.iter())
+        .map(|(w, f)| w * f)
+        .sum();
+}
+
+pub fn load_weights(path: &Path) -> io::Result<[f32; NUM_FEATURES]> {
+    let text = fs::read_to_string(path)?;
+    let mut weights: Vec<f32> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.parse() {
+            Ok(w) => weights.push(w),
+            Err(e) => return Err(Error::new(ErrorKind::InvalidData, e)),
+        }
+    }
+    return weights.try_into().map_err(|w: Vec<f32>| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{} weights are expected, but {} were found",
+                NUM_FEATURES,
+                w.len()
+            ),
+        )
+    });
+}
+
+pub fn save_weights(path: &Path, weights: &[f32; NUM_FEATURES]) -> io::Result<()> {
+    let mut text = String::from("# Weights of the linear Q-learning agent\n");
+    for w in weights {
+        text.push_str(&format!("{}\n", w));
+    }
+    return fs::write(path, text);
+}
+
+pub struct LinearQAgent {
+    hand: Vec<i32>,
+    weights: [f32; NUM_FEATURES],
+    epsilon: f64,
+    rng: ChaCha8Rng,
+}
+
+impl LinearQAgent {
+    pub fn new(weights: [f32; NUM_FEATURES], epsilon: f64) -> Self {
+        Self {
+            hand: Vec::new(),
+            weights,
+            epsilon,
+            rng: ChaCha8Rng::from_entropy(),
+        }
+    }
+}
+
+impl Agent for LinearQAgent {
+    fn get_hand(&self) -> &[i32] {
+        &self.hand
+    }
+
+    fn set_hand(&mut self, cards: &[i32]) -> Result<(), HeartsError> {
+        self.hand = check_hand(cards)?;
+        return Ok(());
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    fn select_card(
+        &mut self,
+        whole_card_sequence: &[i32; NUM_CARDS],
+        _whole_agent_sequence: &[i32; NUM_CARDS],
+        trick: usize,
+        turn: usize,
+        bh_flag: bool,
+        rules: &RuleSet,
+        // The time budget is not a feature: a feature that is the same for every candidate card adds the same
+        // value to all of them in a linear model, so it could not change the choice.
+        _budget: TimeBudget,
+    ) -> i32 {
+        let num_players = rules.num_players;
+        let card_sequence =
+            &whole_card_sequence[(trick * num_players)..((trick + 1) * num_players)];
+
+        let valid_cards: Vec<i32> = self
+            .hand
+            .iter()
+            .copied()
+            .filter(|c| {
+                *c != -1 && is_valid_card(&self.hand, card_sequence, *c, trick, bh_flag, rules)
+            })
+            .collect();
+
+        // No card is selected (-1) if none is valid, which the engine reports as an illegal move.
+        if valid_cards.is_empty() {
+            return -1;
+        }
+
+        if self.rng.gen_bool(self.epsilon) {
+            return valid_cards[self.rng.gen_range(0..valid_cards.len())];
+        }
+
+        let mut best_card = valid_cards[0];
+        let mut best_value = f32::NEG_INFINITY;
+        for card in valid_cards {
+            let features = calc_features(
+                &self.hand,
+                whole_card_sequence,
+                trick,
+                turn,
+                bh_flag,
+                card,
+                rules,
+            );
+            let value = calc_value(&self.weights, &features);
+            if value > best_value {
+                best_card = card;
+                best_value = value;
+            }
+        }
+
+        return best_card;
+    }
+
+    fn update_hand(&mut self, card: i32) {
+        for i in 0..self.hand.len() {
+            if self.hand[i] == card {
+                self.hand[i] = -1;
+                break;
+            }
+        }
+    }
+}
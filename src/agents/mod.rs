@@ -0,0 +1,2 @@
+pub mod linear_q_agent;
+pub mod neural_network_agent;
@@ -0,0 +1,392 @@
+//
+// Neural network agent
+//
+// It plays cards according to a small feed-forward network that evaluates the encoded observation
+// (see observation.rs) and outputs a score (logit) for each of the NUM_CARDS cards.
+// The card with the highest score among the valid cards in the hand is played.
+// Forward inference is implemented here, so no GPU or external runtime is required.
+//
+// The weights are loaded from a little-endian binary file:
+//   b"HNN1"                          magic
+//   u32                              number of layers
+//   for each layer:
+//     u32, u32                       number of inputs and outputs
+//     f32 * (outputs * inputs)       weights in row-major order (one row per output)
+//     f32 * outputs                  biases
+// ReLU is applied after every layer except the last one.
+// The first layer must take OBS_SIZE inputs and the last layer must have NUM_CARDS outputs.
+//
+// Such a file can be written from PyTorch, for example, by
+//   f.write(b"HNN1"); f.write(struct.pack("<I", len(layers)))
+//   for l in layers: f.write(struct.pack("<II", l.in_features, l.out_features));
+//                    f.write(l.weight.detach().numpy().astype("<f4").tobytes());
+//                    f.write(l.bias.detach().numpy().astype("<f4").tobytes())
+//
+
+use crate::clock::TimeBudget;
+use crate::error::HeartsError;
+use crate::observation::{encode_observation, legal_mask, OBS_SIZE};
+use crate::rules::RuleSet;
+use crate::{check_hand, Agent, NUM_CARDS};
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"HNN1";
+
+struct Layer {
+    num_inputs: usize,
+    num_outputs: usize,
+    weights: Vec<f32>,
+    biases: Vec<f32>,
+}
+
+impl Layer {
+    fn forward(&self, input: &[f32]) -> Vec<f32> {
+        let mut output = self.biases.clone();
+        for o in 0..self.num_outputs {
+            let row = &self.weights[(o * self.num_inputs)..((o + 1) * self.num_inputs)];
+            for (w, x) in row.iter().zip(input.iter()) {
+                output[o] += w * x;
+            }
+        }
+        return output;
+    }
+}
+
+pub struct Network {
+    layers: Vec<Layer>,
+}
+
+impl Network {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        return Self::from_bytes(&bytes);
+    }
+
+    fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid_data("not a network file (bad magic)"));
+        }
+        let mut reader = ByteReader {
+            bytes,
+            pos: MAGIC.len(),
+        };
+
+        let num_layers = reader.read_u32()? as usize;
+        if num_layers == 0 {
+            return Err(invalid_data("the network has no layer"));
+        }
+
+        let mut layers: Vec<Layer> = Vec::new();
+        for _ in 0..num_layers {
+            let num_inputs = reader.read_u32()? as usize;
+            let num_outputs = reader.read_u32()? as usize;
+            let num_weights = num_inputs
+                .checked_mul(num_outputs)
+                .ok_or_else(|| invalid_data("a layer has too many weights"))?;
+            let weights = reader.read_f32s(num_weights)?;
+            let biases = reader.read_f32s(num_outputs)?;
+            layers.push(Layer {
+                num_inputs,
+                num_outputs,
+                weights,
+                biases,
+            });
+        }
+
+        if reader.pos != bytes.len() {
+            return Err(invalid_data("trailing bytes after the last layer"));
+        }
+
+        // The shapes of the layers must be chained from OBS_SIZE inputs to NUM_CARDS outputs.
+        let mut expected_inputs = OBS_SIZE;
+        for (i, layer) in layers.iter().enumerate() {
+            if layer.num_inputs != expected_inputs {
+                return Err(invalid_data(&format!(
+                    "layer {} takes {} inputs, but {} are expected",
+                    i, layer.num_inputs, expected_inputs
+                )));
+            }
+            expected_inputs = layer.num_outputs;
+        }
+        if expected_inputs != NUM_CARDS {
+            return Err(invalid_data(&format!(
+                "the last layer has {} outputs, but {} are expected",
+                expected_inputs, NUM_CARDS
+            )));
+        }
+
+        return Ok(Self { layers });
+    }
+
+    pub fn forward(&self, input: &[f32]) -> Vec<f32> {
+        let mut x = input.to_vec();
+        for (i, layer) in self.layers.iter().enumerate() {
+            x = layer.forward(&x);
+            if i + 1 < self.layers.len() {
+                for v in x.iter_mut() {
+                    *v = v.max(0.0);
+                }
+            }
+        }
+        return x;
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl ByteReader<'_> {
+    fn take(&mut self, n: usize) -> io::Result<&[u8]> {
+        if self.bytes.len() - self.pos < n {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "the network file is truncated",
+            ));
+        }
+        let slice = &self.bytes[self.pos..(self.pos + n)];
+        self.pos += n;
+        return Ok(slice);
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let b = self.take(4)?;
+        return Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    }
+
+    fn read_f32s(&mut self, n: usize) -> io::Result<Vec<f32>> {
+        // A malformed header can give a count whose size in bytes does not fit in usize.
+        let size = n
+            .checked_mul(4)
+            .ok_or_else(|| invalid_data("a layer has too many values"))?;
+        let b = self.take(size)?;
+        return Ok(b
+            .chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect());
+    }
+}
+
+fn invalid_data(msg: &str) -> Error {
+    return Error::new(ErrorKind::InvalidData, msg.to_string());
+}
+
+pub struct NeuralNetworkAgent {
+    hand: Vec<i32>,
+    network: Network,
+}
+
+impl NeuralNetworkAgent {
+    pub fn new(network: Network) -> Self {
+        Self {
+            hand: Vec::new(),
+            network,
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        return Ok(Self::new(Network::load(path)?));
+    }
+}
+
+impl Agent for NeuralNetworkAgent {
+    fn get_hand(&self) -> &[i32] {
+        &self.hand
+    }
+
+    fn set_hand(&mut self, cards: &[i32]) -> Result<(), HeartsError> {
+        self.hand = check_hand(cards)?;
+        return Ok(());
+    }
+
+    // Selecting the valid card with the highest output of the network.
+    fn select_card(
+        &mut self,
+        whole_card_sequence: &[i32; NUM_CARDS],
+        whole_agent_sequence: &[i32; NUM_CARDS],
+        trick: usize,
+        turn: usize,
+        bh_flag: bool,
+        rules: &RuleSet,
+        budget: TimeBudget,
+    ) -> i32 {
+        let obs = encode_observation(
+            &self.hand,
+            whole_card_sequence,
+            whole_agent_sequence,
+            trick,
+            turn,
+            bh_flag,
+            rules,
+            budget,
+        );
+        let mask = legal_mask(&self.hand, whole_card_sequence, trick, bh_flag, rules);
+        let logits = self.network.forward(&obs);
+
+        let mut best_card = -1;
+        let mut best_logit = f32::NEG_INFINITY;
+        for card in 0..NUM_CARDS {
+            if mask[card] && (best_card == -1 || logits[card] > best_logit) {
+                best_card = card as i32;
+                best_logit = logits[card];
+            }
+        }
+
+        return best_card;
+    }
+
+    fn update_hand(&mut self, card: i32) {
+        for i in 0..self.hand.len() {
+            if self.hand[i] == card {
+                self.hand[i] = -1;
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The bytes of a layer with the given shape, weights and biases.
+    fn layer_bytes(num_inputs: u32, num_outputs: u32, weights: &[f32], biases: &[f32]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend(num_inputs.to_le_bytes());
+        bytes.extend(num_outputs.to_le_bytes());
+        for v in weights.iter().chain(biases.iter()) {
+            bytes.extend(v.to_le_bytes());
+        }
+        return bytes;
+    }
+
+    fn network_bytes(layers: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = MAGIC.to_vec();
+        bytes.extend((layers.len() as u32).to_le_bytes());
+        for layer in layers {
+            bytes.extend(layer);
+        }
+        return bytes;
+    }
+
+    // A layer of the given shape whose weights and biases are all zero.
+    fn zero_layer(num_inputs: usize, num_outputs: usize) -> Vec<u8> {
+        return layer_bytes(
+            num_inputs as u32,
+            num_outputs as u32,
+            &vec![0.0; num_inputs * num_outputs],
+            &vec![0.0; num_outputs],
+        );
+    }
+
+    fn error_kind(bytes: &[u8]) -> ErrorKind {
+        return Network::from_bytes(bytes).err().unwrap().kind();
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut bytes = network_bytes(&[zero_layer(OBS_SIZE, NUM_CARDS)]);
+        bytes[0] = b'X';
+        assert_eq!(error_kind(&bytes), ErrorKind::InvalidData);
+        assert_eq!(error_kind(b"HN"), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_file() {
+        let bytes = network_bytes(&[zero_layer(OBS_SIZE, NUM_CARDS)]);
+        assert_eq!(
+            error_kind(&bytes[..(bytes.len() - 1)]),
+            ErrorKind::UnexpectedEof
+        );
+        assert_eq!(error_kind(&bytes[..6]), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let mut bytes = network_bytes(&[zero_layer(OBS_SIZE, NUM_CARDS)]);
+        bytes.push(0);
+        assert_eq!(error_kind(&bytes), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn mismatched_layer_shapes() {
+        let cases = [
+            vec![zero_layer(OBS_SIZE, 8), zero_layer(9, NUM_CARDS)],
+            vec![zero_layer(OBS_SIZE + 1, NUM_CARDS)],
+            vec![zero_layer(OBS_SIZE, NUM_CARDS - 1)],
+            vec![],
+        ];
+        for layers in cases.iter() {
+            assert_eq!(error_kind(&network_bytes(layers)), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn overflowing_header() {
+        // The second layer claims 2^31 x 2^31 weights, whose size in bytes does not fit in usize.
+        let mut bytes = network_bytes(&[zero_layer(OBS_SIZE, 8)]);
+        bytes[4..8].copy_from_slice(&2u32.to_le_bytes());
+        bytes.extend((1u32 << 31).to_le_bytes());
+        bytes.extend((1u32 << 31).to_le_bytes());
+        assert_eq!(error_kind(&bytes), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn forward() {
+        // The hidden units are ReLU(x[0]) and ReLU(-x[0]), and output o is o * (h[0] - h[1]) + 1.
+        let mut hidden_weights = vec![0.0; 2 * OBS_SIZE];
+        hidden_weights[0] = 1.0;
+        hidden_weights[OBS_SIZE] = -1.0;
+        let output_weights: Vec<f32> = (0..NUM_CARDS)
+            .flat_map(|o| [o as f32, -(o as f32)])
+            .collect();
+        let bytes = network_bytes(&[
+            layer_bytes(OBS_SIZE as u32, 2, &hidden_weights, &[0.0, 0.0]),
+            layer_bytes(2, NUM_CARDS as u32, &output_weights, &[1.0; NUM_CARDS]),
+        ]);
+        let network = Network::from_bytes(&bytes).unwrap();
+
+        let mut input = vec![0.0; OBS_SIZE];
+        for x in [2.0, -3.0] {
+            input[0] = x;
+            let expected: Vec<f32> = (0..NUM_CARDS).map(|o| (o as f32) * x + 1.0).collect();
+            assert_eq!(network.forward(&input), expected);
+        }
+    }
+
+    #[test]
+    fn best_legal_card_is_selected() {
+        // The output of each card is its number, so the highest legal card is selected.
+        let biases: Vec<f32> = (0..NUM_CARDS).map(|c| c as f32).collect();
+        let bytes = network_bytes(&[layer_bytes(
+            OBS_SIZE as u32,
+            NUM_CARDS as u32,
+            &vec![0.0; OBS_SIZE * NUM_CARDS],
+            &biases,
+        )]);
+        let mut agent = NeuralNetworkAgent::new(Network::from_bytes(&bytes).unwrap());
+
+        // Leading C-5, D-3 or H-9 in the second trick, after C-2, C-3, C-4 and C-6;
+        // hearts may not be led before they are broken.
+        let mut whole_card_sequence = [-1; NUM_CARDS];
+        let mut whole_agent_sequence = [-1; NUM_CARDS];
+        for (i, card) in [0, 1, 2, 4].iter().enumerate() {
+            whole_card_sequence[i] = *card;
+            whole_agent_sequence[i] = i as i32;
+        }
+        agent.set_hand(&[3, 14, 46]).unwrap();
+        let card = agent.select_card(
+            &whole_card_sequence,
+            &whole_agent_sequence,
+            1,
+            0,
+            false,
+            &RuleSet::default(),
+            TimeBudget::default(),
+        );
+        assert_eq!(card, 14);
+    }
+}
@@ -0,0 +1,226 @@
+//
+// Post-game blunder analyzer
+//
+// Every decision in a saved game is replayed and each valid card is evaluated by sampling rollouts:
+// the game is played out NUM_ROLLOUTS times from the decision point with the actual hands of the record,
+// where every player is a rule-based agent that plays a random valid card with probability ROLLOUT_EPSILON.
+// The agents do not look at the other hands, so this is not a double dummy search.
+// The random choices are drawn from the given seed, so that the same game and seed give the same report.
+// The expected penalty points of the player (of the player's team in partnership mode) are the averaged
+// penalty points at the end of the rollouts,
+// and a play is flagged as a blunder when it is worse than the best alternative by BLUNDER_THRESHOLD or more.
+// A renege (accepted when the rules allow it) is rolled out as well, with the renege points added to its value,
+// and is marked in the report.
+//
+
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::clock::TimeBudget;
+use crate::error::HeartsError;
+use crate::legality::{legal_moves, why_illegal, GameError, GameState, IllegalReason};
+use crate::record::GameRecord;
+use crate::replay::replay_game;
+use crate::rules::RuleSet;
+use crate::{calc_penalty_points, Agent, RuleBasedAgent, CARD_NAME};
+
+const NUM_ROLLOUTS: usize = 100;
+const ROLLOUT_EPSILON: f64 = 0.1;
+const BLUNDER_THRESHOLD: f32 = 3.0;
+
+struct Evaluation {
+    seat: usize,
+    card: i32,
+    renege: bool,
+    expected: f32,
+    best_card: i32,
+    best_expected: f32,
+}
+
+pub fn analyze(record: &GameRecord, seed: u64) -> Result<(), HeartsError> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let whole_card_sequence = record.whole_card_sequence();
+    let whole_agent_sequence = record.whole_agent_sequence();
+    let rules = &record.rules;
+    let num_players = rules.num_players;
+    let hand_size = rules.get_hand_size();
+
+    // The hands of all players are tracked during the replay, since the search needs all of them.
+    let mut hands: Vec<Vec<i32>> = Vec::new();
+    for i in 0..num_players {
+        let mut hand = record.dealt_cards[(i * hand_size)..((i + 1) * hand_size)].to_vec();
+        hand.sort();
+        hands.push(hand);
+    }
+
+    let mut evaluations: Vec<Result<Evaluation, GameError>> = Vec::new();
+    replay_game(
+        &record.dealt_cards,
+        &whole_card_sequence,
+        &whole_agent_sequence,
+        rules,
+        |d| {
+            let state = GameState {
+                hands: hands.clone(),
+                whole_card_sequence: *d.whole_card_sequence,
+                whole_agent_sequence: *d.whole_agent_sequence,
+                idx: d.trick * num_players + d.turn,
+                seat: d.seat,
+                bh_flag: d.bh_flag,
+            };
+            evaluations.push(evaluate_decision(&state, d.card, rules, &mut rng));
+            remove_card(&mut hands[d.seat], d.card);
+        },
+    );
+
+    let evaluations: Vec<Evaluation> = evaluations.into_iter().collect::<Result<_, _>>()?;
+
+    let penalty_points = calc_penalty_points(&whole_card_sequence, &whole_agent_sequence, rules);
+    let mut num_blunders: Vec<i32> = vec![0; num_players];
+    let mut lost_points: Vec<f32> = vec![0.0; num_players];
+
+    for trick in 0..hand_size {
+        println!("== trick {} ==", trick + 1);
+        for turn in 0..num_players {
+            let e = &evaluations[trick * num_players + turn];
+            print!(
+                "Agent {}: {:<5} expected {:5.2}",
+                e.seat + 1,
+                CARD_NAME[e.card as usize],
+                e.expected
+            );
+            if e.renege {
+                print!("  !! renege");
+            }
+            if e.expected - e.best_expected >= BLUNDER_THRESHOLD {
+                num_blunders[e.seat] += 1;
+                lost_points[e.seat] += e.expected - e.best_expected;
+                print!(
+                    "  ?? blunder: {} was expected {:5.2}",
+                    CARD_NAME[e.best_card as usize], e.best_expected
+                );
+            }
+            println!();
+        }
+        println!();
+    }
+
+    println!("Penalty points: {:?}", penalty_points);
+    for i in 0..num_players {
+        println!(
+            "Agent {}: {} blunder(s), {:.2} expected penalty points lost",
+            i + 1,
+            num_blunders[i],
+            lost_points[i]
+        );
+    }
+    return Ok(());
+}
+
+fn evaluate_decision(
+    state: &GameState,
+    played_card: i32,
+    rules: &RuleSet,
+    rng: &mut ChaCha8Rng,
+) -> Result<Evaluation, GameError> {
+    let mut expected = 0.0;
+    let mut best_card = played_card;
+    let mut best_expected = f32::INFINITY;
+
+    // The played card is evaluated even if it is not legal, which is the case for a renege.
+    let renege =
+        why_illegal(state, state.seat, played_card, rules) == Some(IllegalReason::MustFollowSuit);
+    let mut cards = legal_moves(state, state.seat, rules);
+    if renege {
+        cards.push(played_card);
+    }
+
+    for card in cards {
+        let mut total = 0;
+        for _ in 0..NUM_ROLLOUTS {
+            total += rollout(state, card, rules, rng)?;
+        }
+        let mut value = (total as f32) / (NUM_ROLLOUTS as f32);
+        if card == played_card && renege {
+            value += rules.renege_points as f32;
+        }
+        if card == played_card {
+            expected = value;
+        }
+        if value < best_expected {
+            best_card = card;
+            best_expected = value;
+        }
+    }
+
+    return Ok(Evaluation {
+        seat: state.seat,
+        card: played_card,
+        renege,
+        expected,
+        best_card,
+        best_expected,
+    });
+}
+
+// Playing out the game after the player plays the given card, and returning the penalty points of the player,
+// or of the player's team in partnership mode so that a card dumped on the partner is not free.
+fn rollout(
+    state: &GameState,
+    first_card: i32,
+    rules: &RuleSet,
+    rng: &mut ChaCha8Rng,
+) -> Result<i32, GameError> {
+    let mut s = state.clone();
+    let num_players = rules.num_players;
+
+    let mut agents: Vec<RuleBasedAgent> = Vec::new();
+    for i in 0..num_players {
+        let mut agent = RuleBasedAgent::new();
+        agent.on_game_start(i, rules);
+        agent
+            .set_hand(&s.hands[i])
+            .expect("the hands of a loaded record are valid");
+        agent.set_partner(rules.get_partner(i));
+        agents.push(agent);
+    }
+
+    let mut card = first_card;
+    loop {
+        agents[s.seat].update_hand(card);
+        s.play(card, rules)?;
+        if s.is_finished() {
+            break;
+        }
+
+        card = if rng.gen_bool(ROLLOUT_EPSILON) {
+            let valid_cards = legal_moves(&s, s.seat, rules);
+            valid_cards.choose(rng).copied().unwrap_or(-1)
+        } else {
+            agents[s.seat].select_card(
+                &s.whole_card_sequence,
+                &s.whole_agent_sequence,
+                s.get_trick(),
+                s.idx % num_players,
+                s.bh_flag,
+                rules,
+                TimeBudget::default(),
+            )
+        };
+    }
+
+    let penalty_points =
+        calc_penalty_points(&s.whole_card_sequence, &s.whole_agent_sequence, rules);
+    let team_points = rules.get_team_points(&penalty_points);
+    return Ok(team_points[state.seat % rules.get_num_teams()]);
+}
+
+fn remove_card(hand: &mut [i32], card: i32) {
+    for i in 0..hand.len() {
+        if hand[i] == card {
+            hand[i] = -1;
+            break;
+        }
+    }
+}
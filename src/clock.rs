@@ -0,0 +1,61 @@
+//
+// Clock of the game driver
+//
+// Each agent may think for move_time_ms per move and for game_time_ms in total per game (0 means no limit).
+// The time an agent may use for the current move and the time left for the rest of the game are passed to
+// `Agent::select_card` as a TimeBudget, and are part of the observation (see observation.rs).
+// Agents are not interrupted; a card chosen after the time limit is discarded and the fallback card
+// given by the rules is played instead.
+//
+
+use std::time::Duration;
+
+use crate::rules::RuleSet;
+
+// The time an agent may use for the current move, which is also bounded by the time left for the game,
+// and the time left for the game; None means no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TimeBudget {
+    pub move_limit: Option<Duration>,
+    pub game_left: Option<Duration>,
+}
+
+pub struct Clock {
+    move_limit: Option<Duration>,
+    game_limit: Option<Duration>,
+    used: Vec<Duration>,
+}
+
+impl Clock {
+    pub fn new(rules: &RuleSet) -> Self {
+        let limit = |ms: u64| (ms > 0).then(|| Duration::from_millis(ms));
+        Self {
+            move_limit: limit(rules.move_time_ms),
+            game_limit: limit(rules.game_time_ms),
+            used: vec![Duration::ZERO; rules.num_players],
+        }
+    }
+
+    // The time budget of the agent at the seat for the next move.
+    pub fn get_budget(&self, seat: usize) -> TimeBudget {
+        let game_left = self
+            .game_limit
+            .map(|limit| limit.saturating_sub(self.used[seat]));
+        let move_limit = match (self.move_limit, game_left) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        return TimeBudget {
+            move_limit,
+            game_left,
+        };
+    }
+
+    // Charging the agent at the seat with the time used for a move, and returning whether it was in time.
+    // An agent that had no time left is never in time.
+    pub fn charge(&mut self, seat: usize, elapsed: Duration) -> bool {
+        let limit = self.get_budget(seat).move_limit;
+        self.used[seat] += elapsed;
+        return limit.is_none_or(|limit| !limit.is_zero() && elapsed <= limit);
+    }
+}
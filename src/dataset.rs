@@ -0,0 +1,212 @@
+//
+// Self-play dataset exporter
+//
+// Agents play many games and every decision is written as one record:
+// the encoded observation, the legal mask, the card chosen by the agent and
+// the penalty points that the agent finally acquired in the game.
+// The records are written as NumPy arrays (observations.npy, masks.npy, actions.npy and outcomes.npy)
+// for supervised learning of policy and value networks, and as decisions.csv for inspection.
+//
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::clock::TimeBudget;
+use crate::observation::{encode_observation, legal_mask, OBS_SIZE};
+use crate::replay::replay_game;
+use crate::rules::RuleSet;
+use crate::{calc_penalty_points, play_one_game, Agent, NUM_CARDS};
+
+struct Decision {
+    game: usize,
+    seat: usize,
+    trick: usize,
+    turn: usize,
+    observation: [f32; OBS_SIZE],
+    mask: [bool; NUM_CARDS],
+    action: i32,
+    outcome: i32,
+}
+
+pub fn export_dataset(
+    agents: &mut [Box<dyn Agent>],
+    rules: &RuleSet,
+    num_games: usize,
+    out_dir: &Path,
+) -> io::Result<()> {
+    let mut decisions: Vec<Decision> = Vec::new();
+
+    for game in 1..=num_games {
+        let mut whole_card_sequence: [i32; NUM_CARDS] = [-1; NUM_CARDS];
+        let mut whole_agent_sequence: [i32; NUM_CARDS] = [-1; NUM_CARDS];
+
+        // The outcomes are the penalty points of the cards taken; those for illegal moves are not included.
+        let mut extra_penalty_points: Vec<i32> = vec![0; rules.num_players];
+        let dealt_cards = play_one_game(
+            agents,
+            rules,
+            game,
+            rand::random(),
+            &mut whole_card_sequence,
+            &mut whole_agent_sequence,
+            &mut extra_penalty_points,
+        )
+        .map_err(io::Error::other)?;
+
+        collect_decisions(
+            game,
+            &dealt_cards,
+            &whole_card_sequence,
+            &whole_agent_sequence,
+            rules,
+            &mut decisions,
+        );
+    }
+
+    fs::create_dir_all(out_dir)?;
+    write_npy_files(&decisions, out_dir)?;
+    write_csv(&decisions, &out_dir.join("decisions.csv"))?;
+
+    println!(
+        "{} decisions of {} games were written to {}",
+        decisions.len(),
+        num_games,
+        out_dir.display()
+    );
+
+    return Ok(());
+}
+
+fn collect_decisions(
+    game: usize,
+    dealt_cards: &[i32],
+    whole_card_sequence: &[i32; NUM_CARDS],
+    whole_agent_sequence: &[i32; NUM_CARDS],
+    rules: &RuleSet,
+    decisions: &mut Vec<Decision>,
+) {
+    let penalty_points = calc_penalty_points(whole_card_sequence, whole_agent_sequence, rules);
+
+    replay_game(
+        dealt_cards,
+        whole_card_sequence,
+        whole_agent_sequence,
+        rules,
+        |d| {
+            decisions.push(Decision {
+                game,
+                seat: d.seat,
+                trick: d.trick,
+                turn: d.turn,
+                observation: encode_observation(
+                    d.hand,
+                    d.whole_card_sequence,
+                    d.whole_agent_sequence,
+                    d.trick,
+                    d.turn,
+                    d.bh_flag,
+                    rules,
+                    TimeBudget::default(),
+                ),
+                mask: legal_mask(d.hand, d.whole_card_sequence, d.trick, d.bh_flag, rules),
+                action: d.card,
+                outcome: penalty_points[d.seat],
+            });
+        },
+    );
+}
+
+fn write_npy_files(decisions: &[Decision], out_dir: &Path) -> io::Result<()> {
+    let n = decisions.len();
+
+    let mut w = create_npy(&out_dir.join("observations.npy"), "<f4", &[n, OBS_SIZE])?;
+    for d in decisions {
+        for v in d.observation {
+            w.write_all(&v.to_le_bytes())?;
+        }
+    }
+    w.flush()?;
+
+    let mut w = create_npy(&out_dir.join("masks.npy"), "|u1", &[n, NUM_CARDS])?;
+    for d in decisions {
+        for v in d.mask {
+            w.write_all(&[v as u8])?;
+        }
+    }
+    w.flush()?;
+
+    let mut w = create_npy(&out_dir.join("actions.npy"), "<i4", &[n])?;
+    for d in decisions {
+        w.write_all(&d.action.to_le_bytes())?;
+    }
+    w.flush()?;
+
+    let mut w = create_npy(&out_dir.join("outcomes.npy"), "<i4", &[n])?;
+    for d in decisions {
+        w.write_all(&d.outcome.to_le_bytes())?;
+    }
+    w.flush()?;
+
+    return Ok(());
+}
+
+// Creating a .npy file (format version 1.0) and writing its header; the caller writes the data in C order.
+fn create_npy(path: &Path, descr: &str, shape: &[usize]) -> io::Result<BufWriter<File>> {
+    let shape_str = if shape.len() == 1 {
+        format!("({},)", shape[0])
+    } else {
+        let dims: Vec<String> = shape.iter().map(|d| d.to_string()).collect();
+        format!("({})", dims.join(", "))
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        descr, shape_str
+    );
+
+    // The magic string, the version and the header length take 10 bytes,
+    // and the whole header must be padded with spaces to a multiple of 64 bytes, ending with a newline.
+    let total = 10 + header.len() + 1;
+    let padding = (64 - total % 64) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    let mut w = BufWriter::new(File::create(path)?);
+    w.write_all(b"\x93NUMPY")?;
+    w.write_all(&[1, 0])?;
+    w.write_all(&(header.len() as u16).to_le_bytes())?;
+    w.write_all(header.as_bytes())?;
+
+    return Ok(w);
+}
+
+fn write_csv(decisions: &[Decision], path: &Path) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+
+    write!(w, "game,seat,trick,turn,action,outcome")?;
+    for i in 0..OBS_SIZE {
+        write!(w, ",obs_{}", i)?;
+    }
+    for i in 0..NUM_CARDS {
+        write!(w, ",mask_{}", i)?;
+    }
+    writeln!(w)?;
+
+    for d in decisions {
+        write!(
+            w,
+            "{},{},{},{},{},{}",
+            d.game, d.seat, d.trick, d.turn, d.action, d.outcome
+        )?;
+        for v in d.observation {
+            write!(w, ",{}", v)?;
+        }
+        for v in d.mask {
+            write!(w, ",{}", v as u8)?;
+        }
+        writeln!(w)?;
+    }
+    w.flush()?;
+
+    return Ok(());
+}
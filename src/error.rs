@@ -0,0 +1,70 @@
+//
+// Errors
+//
+// Every operation that takes input from outside the engine (agents, files, the command line)
+// reports a problem with HeartsError instead of panicking, so that an embedding application can recover.
+//
+
+use std::fmt;
+use std::io;
+
+use crate::legality::GameError;
+
+#[derive(Debug)]
+pub enum HeartsError {
+    // The agent number given in the lineup is not one of the known agents.
+    UnknownAgent(i32),
+    // The spec of an agent in the ladder is not an agent number optionally followed by ':' and a profile.
+    InvalidAgentSpec(String),
+    // The number of agents does not match the number of players of the rule set.
+    WrongNumberOfAgents { expected: usize, actual: usize },
+    // The value is not a card (from 0 to 51).
+    InvalidCard(i32),
+    // The card appears more than once in a hand.
+    DuplicateCard(i32),
+    // The agent at the seat passed cards that are not in its hand, or a wrong number of cards.
+    InvalidPass { seat: usize },
+    // An agent played a card that is not allowed.
+    Game(GameError),
+    // A saved game is inconsistent.
+    InvalidRecord(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for HeartsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            HeartsError::UnknownAgent(number) => write!(f, "unknown agent number {}", number),
+            HeartsError::InvalidAgentSpec(spec) => write!(f, "invalid agent spec {}", spec),
+            HeartsError::WrongNumberOfAgents { expected, actual } => {
+                write!(
+                    f,
+                    "{} agents are needed, but {} are given",
+                    expected, actual
+                )
+            }
+            HeartsError::InvalidCard(card) => write!(f, "{} is not a card", card),
+            HeartsError::DuplicateCard(card) => write!(f, "card {} appears more than once", card),
+            HeartsError::InvalidPass { seat } => {
+                write!(f, "Agent {} passed invalid cards", seat + 1)
+            }
+            HeartsError::Game(e) => write!(f, "{}", e),
+            HeartsError::InvalidRecord(message) => write!(f, "invalid record: {}", message),
+            HeartsError::Io(e) => write!(f, "{}", e),
+        };
+    }
+}
+
+impl std::error::Error for HeartsError {}
+
+impl From<GameError> for HeartsError {
+    fn from(e: GameError) -> Self {
+        HeartsError::Game(e)
+    }
+}
+
+impl From<io::Error> for HeartsError {
+    fn from(e: io::Error) -> Self {
+        HeartsError::Io(e)
+    }
+}
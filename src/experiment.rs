@@ -0,0 +1,303 @@
+//
+// Experiments
+//
+// An experiment file (TOML, YAML or JSON) declares everything that determines the results of a run:
+// the lineup of agents with their parameters, the rule set, the number of games and the seed,
+// together with the number of threads and where the results are written.
+// Every game is dealt from a seed derived from the seed of the experiment and the number of the game,
+// so the results do not depend on the number of threads.
+// The results are tagged with the hash of the experiment and the version of the program,
+// so that a result file can be traced back to the configuration and the code that produced it.
+// The hash covers the constants in the profiles and the contents of the model files rather than their paths,
+// which are resolved relative to the experiment file.
+//
+
+use std::fs;
+use std::io::{self, Error};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::HeartsError;
+use crate::export::GameSummary;
+use crate::record::GameRecord;
+use crate::rules::RuleSet;
+use crate::{
+    calc_penalty_points, create_agent, load_config, play_one_game, RuleBasedParams, MODEL_PATH,
+    NUM_CARDS, Q_WEIGHTS_PATH,
+};
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LineupEntry {
+    // Agent number (see idx in main).
+    pub agent: i32,
+    // Path of a profile of the rule-based agent; it is replaced with its constants when the experiment is loaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    // Constants of the rule-based agent; the hand-picked values are used if neither these nor a profile is given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<RuleBasedParams>,
+    // Path of the network of agent 3 or the weights of agent 4; MODEL_PATH or Q_WEIGHTS_PATH if it is not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    // Hash of the contents of the model file, computed when the experiment is loaded.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub model_hash: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Outputs {
+    // Directory to which the results are written as <name>-<hash>.json.
+    pub dir: String,
+    // Whether each game is also saved to <name>-<hash>/game_<number>.json in the directory.
+    pub save_games: bool,
+}
+
+impl Default for Outputs {
+    fn default() -> Self {
+        Self {
+            dir: "results".to_string(),
+            save_games: false,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Experiment {
+    pub name: String,
+    // One agent for each player of the rule set, in seat order.
+    pub lineup: Vec<LineupEntry>,
+    #[serde(default)]
+    pub rules: RuleSet,
+    pub games: usize,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default = "default_threads")]
+    pub threads: usize,
+    #[serde(default)]
+    pub outputs: Outputs,
+}
+
+fn default_threads() -> usize {
+    return 1;
+}
+
+// 64-bit FNV-1a hash.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    return hash;
+}
+
+#[derive(Serialize)]
+pub struct ExperimentResult {
+    pub name: String,
+    pub config_hash: String,
+    pub version: String,
+    pub experiment: Experiment,
+    pub averaged_penalty_points: Vec<f64>,
+    // Only in partnership mode; team t consists of seat t and its partner.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub averaged_team_points: Option<Vec<f64>>,
+    pub games: Vec<GameSummary>,
+}
+
+impl Experiment {
+    pub fn load(path: &Path) -> Result<Self, HeartsError> {
+        let mut experiment: Self = load_config(path)?;
+        experiment.rules.validate()?;
+        if experiment.lineup.len() != experiment.rules.num_players {
+            return Err(HeartsError::WrongNumberOfAgents {
+                expected: experiment.rules.num_players,
+                actual: experiment.lineup.len(),
+            });
+        }
+        if experiment.threads == 0 {
+            return Err(
+                Error::new(io::ErrorKind::InvalidData, "threads must be at least 1").into(),
+            );
+        }
+
+        // The profiles are resolved so that the hash depends on the constants rather than on the paths,
+        // and the model files are hashed for the same reason.
+        let base = path.parent().unwrap_or(Path::new(""));
+        for entry in experiment.lineup.iter_mut() {
+            if let Some(profile) = entry.profile.take() {
+                if entry.agent != 2 || entry.params.is_some() {
+                    return Err(HeartsError::InvalidAgentSpec(profile));
+                }
+                entry.params = Some(RuleBasedParams::load(&base.join(profile))?);
+            }
+            let model = match (entry.agent, entry.model.take()) {
+                (3 | 4, Some(model)) => base.join(model),
+                (3, None) => PathBuf::from(MODEL_PATH),
+                (4, None) => PathBuf::from(Q_WEIGHTS_PATH),
+                (_, Some(model)) => return Err(HeartsError::InvalidAgentSpec(model)),
+                (_, None) => continue,
+            };
+            let bytes = fs::read(&model).map_err(|e| {
+                Error::new(
+                    e.kind(),
+                    format!("failed to load {}: {}", model.display(), e),
+                )
+            })?;
+            entry.model_hash = Some(format!("{:016x}", fnv1a(&bytes)));
+            entry.model = Some(model.to_string_lossy().into_owned());
+        }
+        return Ok(experiment);
+    }
+
+    // Hash of the settings that determine the results (the agents with their constants and models, the rules,
+    // the number of games and the seed) over their JSON form.
+    pub fn get_hash(&self) -> String {
+        let lineup: Vec<(i32, Option<RuleBasedParams>, Option<&str>)> = self
+            .lineup
+            .iter()
+            .map(|e| (e.agent, e.params, e.model_hash.as_deref()))
+            .collect();
+        let text = serde_json::to_string(&(&lineup, &self.rules, self.games, self.seed))
+            .expect("an experiment can be serialized");
+        return format!("{:016x}", fnv1a(text.as_bytes()));
+    }
+
+    // The seed of the game with the given number (counted from 1).
+    fn get_game_seed(&self, game: usize) -> u64 {
+        return self
+            .seed
+            .wrapping_add((game as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    }
+}
+
+// Running the experiment on its threads and writing the results, whose path is returned.
+pub fn run_experiment(experiment: &Experiment) -> Result<PathBuf, HeartsError> {
+    let hash = experiment.get_hash();
+    let out_dir = Path::new(&experiment.outputs.dir);
+    let games_dir = out_dir.join(format!("{}-{}", experiment.name, hash));
+    fs::create_dir_all(out_dir)?;
+    if experiment.outputs.save_games {
+        fs::create_dir_all(&games_dir)?;
+    }
+
+    // Thread t plays the games whose numbers are t + 1, t + 1 + threads, and so on, with its own agents.
+    let results: Vec<Result<Vec<GameSummary>, HeartsError>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..experiment.threads)
+            .map(|t| {
+                let games_dir = &games_dir;
+                scope.spawn(move || run_games(experiment, t, games_dir))
+            })
+            .collect();
+        return handles
+            .into_iter()
+            .map(|handle| handle.join().expect("a thread of the experiment panicked"))
+            .collect();
+    });
+    let mut games: Vec<GameSummary> = Vec::new();
+    for result in results {
+        games.extend(result?);
+    }
+    games.sort_by_key(|g| g.game);
+
+    let num_players = experiment.rules.num_players;
+    let mut averaged_penalty_points: Vec<f64> = vec![0.0; num_players];
+    for g in games.iter() {
+        for i in 0..num_players {
+            averaged_penalty_points[i] += g.penalty_points[i] as f64;
+        }
+    }
+    for i in 0..num_players {
+        averaged_penalty_points[i] /= experiment.games.max(1) as f64;
+    }
+    let rules = &experiment.rules;
+    let averaged_team_points = rules.partnership.then(|| {
+        let mut team_points: Vec<f64> = vec![0.0; rules.get_num_teams()];
+        for g in games.iter() {
+            for (t, p) in rules.get_team_points(&g.penalty_points).iter().enumerate() {
+                team_points[t] += *p as f64;
+            }
+        }
+        team_points
+            .iter()
+            .map(|p| p / experiment.games.max(1) as f64)
+            .collect()
+    });
+
+    let result = ExperimentResult {
+        name: experiment.name.clone(),
+        config_hash: hash.clone(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        experiment: experiment.clone(),
+        averaged_penalty_points,
+        averaged_team_points,
+        games,
+    };
+    let path = out_dir.join(format!("{}-{}.json", experiment.name, hash));
+    let text = serde_json::to_string_pretty(&result).map_err(Error::other)?;
+    fs::write(&path, text)?;
+    return Ok(path);
+}
+
+fn run_games(
+    experiment: &Experiment,
+    thread: usize,
+    games_dir: &Path,
+) -> Result<Vec<GameSummary>, HeartsError> {
+    let rules = &experiment.rules;
+    let seats: Vec<i32> = experiment.lineup.iter().map(|e| e.agent).collect();
+    let mut agents = Vec::new();
+    for entry in experiment.lineup.iter() {
+        agents.push(create_agent(
+            entry.agent,
+            entry.params.unwrap_or_default(),
+            entry.model.as_deref().map(Path::new),
+        )?);
+    }
+
+    let mut results: Vec<GameSummary> = Vec::new();
+    for game in ((thread + 1)..=experiment.games).step_by(experiment.threads) {
+        let seed = experiment.get_game_seed(game);
+        let mut whole_card_sequence: [i32; NUM_CARDS] = [-1; NUM_CARDS];
+        let mut whole_agent_sequence: [i32; NUM_CARDS] = [-1; NUM_CARDS];
+        let mut extra_penalty_points: Vec<i32> = vec![0; rules.num_players];
+        let dealt_cards = play_one_game(
+            &mut agents,
+            rules,
+            game,
+            seed,
+            &mut whole_card_sequence,
+            &mut whole_agent_sequence,
+            &mut extra_penalty_points,
+        )?;
+
+        if experiment.outputs.save_games {
+            let record = GameRecord::new(
+                &dealt_cards,
+                &whole_card_sequence,
+                &whole_agent_sequence,
+                rules,
+            );
+            record.save(&games_dir.join(format!("game_{}.json", game)))?;
+        }
+
+        let mut penalty_points =
+            calc_penalty_points(&whole_card_sequence, &whole_agent_sequence, rules);
+        for i in 0..rules.num_players {
+            penalty_points[i] += extra_penalty_points[i];
+        }
+        results.push(GameSummary::new(
+            game,
+            seed,
+            &seats,
+            &whole_card_sequence,
+            &whole_agent_sequence,
+            &penalty_points,
+            rules,
+        ));
+    }
+    return Ok(results);
+}
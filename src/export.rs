@@ -0,0 +1,211 @@
+//
+// Result export
+//
+// The results of the games are written as CSV or JSON (by the extension of the path, .json for JSON and CSV otherwise)
+// so that they can be loaded into notebooks and dashboards:
+// one entry per game with the seed, the agent at each seat, the penalty points, the player who shot the moon
+// and the player who took S-Q, and a summary with the metrics printed at the end of a run.
+// Seats are counted from 1 in CSV, as in the printed output, and from 0 in JSON.
+//
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Error};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::rules::RuleSet;
+use crate::{get_card_taker, get_moon_shooter, NUM_CARDS, S_Q};
+
+#[derive(Serialize)]
+pub struct GameSummary {
+    pub game: usize,
+    pub seed: u64,
+    // Agent number at each seat (see idx in main).
+    pub seats: Vec<i32>,
+    pub penalty_points: Vec<i32>,
+    pub moon_shooter: Option<usize>,
+    pub sq_taker: Option<usize>,
+}
+
+impl GameSummary {
+    // The penalty points are given as scored, including those for illegal moves and reneges.
+    pub fn new(
+        game: usize,
+        seed: u64,
+        seats: &[i32],
+        whole_card_sequence: &[i32; NUM_CARDS],
+        whole_agent_sequence: &[i32; NUM_CARDS],
+        penalty_points: &[i32],
+        rules: &RuleSet,
+    ) -> Self {
+        Self {
+            game,
+            seed,
+            seats: seats.to_vec(),
+            penalty_points: penalty_points.to_vec(),
+            moon_shooter: get_moon_shooter(whole_card_sequence, whole_agent_sequence, rules),
+            sq_taker: get_card_taker(S_Q, whole_card_sequence, whole_agent_sequence, rules),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Summary {
+    pub games: usize,
+    pub seats: Vec<i32>,
+    pub averaged_penalty_points: Vec<f32>,
+    pub averaged_penalty_ratio: Vec<f32>,
+    // Number of games in which each player shot the moon or took S-Q.
+    pub moon_shots: Vec<usize>,
+    pub sq_taken: Vec<usize>,
+    // Only in partnership mode; team t consists of seat t and its partner.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub averaged_team_points: Option<Vec<f32>>,
+    // Only when the rule set has an end score; the number of matches won by each team (or player).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_wins: Option<Vec<i32>>,
+}
+
+impl Summary {
+    pub fn new(games: &[GameSummary], seats: &[i32], match_wins: &[i32], rules: &RuleSet) -> Self {
+        let num_players = rules.num_players;
+        let num_games = games.len().max(1) as f32;
+
+        let mut averaged_penalty_points: Vec<f32> = vec![0.0; num_players];
+        let mut moon_shots: Vec<usize> = vec![0; num_players];
+        let mut sq_taken: Vec<usize> = vec![0; num_players];
+        for g in games.iter() {
+            for i in 0..num_players {
+                averaged_penalty_points[i] += g.penalty_points[i] as f32;
+            }
+            if let Some(s) = g.moon_shooter {
+                moon_shots[s] += 1;
+            }
+            if let Some(s) = g.sq_taker {
+                sq_taken[s] += 1;
+            }
+        }
+        for i in 0..num_players {
+            averaged_penalty_points[i] /= num_games;
+        }
+        let sum: f32 = averaged_penalty_points.iter().sum();
+        let averaged_penalty_ratio: Vec<f32> =
+            averaged_penalty_points.iter().map(|p| p / sum).collect();
+
+        let averaged_team_points = rules.partnership.then(|| {
+            let mut team_points: Vec<i32> = vec![0; rules.get_num_teams()];
+            for g in games.iter() {
+                for (t, p) in rules.get_team_points(&g.penalty_points).iter().enumerate() {
+                    team_points[t] += p;
+                }
+            }
+            team_points
+                .iter()
+                .map(|p| (*p as f32) / num_games)
+                .collect()
+        });
+
+        Self {
+            games: games.len(),
+            seats: seats.to_vec(),
+            averaged_penalty_points,
+            averaged_penalty_ratio,
+            moon_shots,
+            sq_taken,
+            averaged_team_points,
+            match_wins: (rules.end_score > 0).then(|| match_wins.to_vec()),
+        }
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    return path.extension().and_then(|ext| ext.to_str()) == Some("json");
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    let text = serde_json::to_string_pretty(value).map_err(Error::other)?;
+    return fs::write(path, text);
+}
+
+fn format_seat(seat: Option<usize>) -> String {
+    return seat.map(|s| (s + 1).to_string()).unwrap_or_default();
+}
+
+// Writing one entry per game; the CSV has a column for the agent and the penalty points of each seat.
+pub fn export_games(path: &Path, games: &[GameSummary], rules: &RuleSet) -> io::Result<()> {
+    if is_json(path) {
+        return write_json(path, &games);
+    }
+
+    let num_players = rules.num_players;
+    let mut text = String::from("game,seed");
+    for i in 1..=num_players {
+        write!(text, ",agent_{}", i).unwrap();
+    }
+    for i in 1..=num_players {
+        write!(text, ",penalty_{}", i).unwrap();
+    }
+    text.push_str(",moon_shooter,sq_taker\n");
+
+    for g in games.iter() {
+        write!(text, "{},{}", g.game, g.seed).unwrap();
+        for a in g.seats.iter() {
+            write!(text, ",{}", a).unwrap();
+        }
+        for p in g.penalty_points.iter() {
+            write!(text, ",{}", p).unwrap();
+        }
+        writeln!(
+            text,
+            ",{},{}",
+            format_seat(g.moon_shooter),
+            format_seat(g.sq_taker)
+        )
+        .unwrap();
+    }
+    return fs::write(path, text);
+}
+
+// Writing the summary; the CSV has a row for each seat, with the team metrics of the team of the seat.
+pub fn export_summary(path: &Path, summary: &Summary, rules: &RuleSet) -> io::Result<()> {
+    if is_json(path) {
+        return write_json(path, summary);
+    }
+
+    let mut text = String::from(
+        "seat,agent,games,averaged_penalty_points,averaged_penalty_ratio,moon_shots,sq_taken",
+    );
+    if summary.averaged_team_points.is_some() {
+        text.push_str(",averaged_team_points");
+    }
+    if summary.match_wins.is_some() {
+        text.push_str(",match_wins");
+    }
+    text.push('\n');
+
+    for i in 0..rules.num_players {
+        let team = i % rules.get_num_teams();
+        write!(
+            text,
+            "{},{},{},{},{},{},{}",
+            i + 1,
+            summary.seats[i],
+            summary.games,
+            summary.averaged_penalty_points[i],
+            summary.averaged_penalty_ratio[i],
+            summary.moon_shots[i],
+            summary.sq_taken[i]
+        )
+        .unwrap();
+        if let Some(team_points) = &summary.averaged_team_points {
+            write!(text, ",{}", team_points[team]).unwrap();
+        }
+        if let Some(match_wins) = &summary.match_wins {
+            write!(text, ",{}", match_wins[team]).unwrap();
+        }
+        text.push('\n');
+    }
+    return fs::write(path, text);
+}
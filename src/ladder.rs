@@ -0,0 +1,194 @@
+//
+// Rating ladder
+//
+// Agents are registered in a ladder file by their specs: the agent number (see idx in main),
+// optionally followed by ':' and the path of a profile for a rule-based agent, e.g. "1" or "2:profiles/default.toml".
+// Each game is played by the registered agents that have played the fewest games, at random seats,
+// and the ratings are updated by pairwise Elo from the finishing order by penalty points:
+// every pair of players in a game is treated as a match, which the player with fewer penalty points wins.
+// In partnership mode, the penalty points of the teams are compared instead, and partners are not matched.
+//
+
+use std::cmp::Ordering;
+use std::fs;
+use std::io::{self, Error};
+use std::path::Path;
+
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::error::HeartsError;
+use crate::rules::RuleSet;
+use crate::{calc_penalty_points, create_agent, play_one_game, Agent, RuleBasedParams, NUM_CARDS};
+
+const INITIAL_RATING: f64 = 1500.0;
+
+// Maximum change of a rating in a game, shared among the pairwise matches of the player.
+const K_FACTOR: f64 = 32.0;
+
+#[derive(Serialize, Deserialize)]
+pub struct LadderEntry {
+    pub spec: String,
+    pub rating: f64,
+    pub games: usize,
+    pub total_penalty_points: i64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Ladder {
+    pub entries: Vec<LadderEntry>,
+}
+
+impl Ladder {
+    // Loading the ladder file; a new ladder is started if it does not exist.
+    pub fn load(path: &Path) -> Result<Self, HeartsError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(path)?;
+        return serde_json::from_str(&text)
+            .map_err(|e| HeartsError::Io(Error::new(io::ErrorKind::InvalidData, e)));
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = serde_json::to_string_pretty(self).map_err(Error::other)?;
+        return fs::write(path, text);
+    }
+
+    // Registering the agent unless it is already in the ladder.
+    pub fn register(&mut self, spec: &str) -> Result<(), HeartsError> {
+        create_agent_from_spec(spec)?;
+        if self.entries.iter().all(|e| e.spec != spec) {
+            self.entries.push(LadderEntry {
+                spec: spec.to_string(),
+                rating: INITIAL_RATING,
+                games: 0,
+                total_penalty_points: 0,
+            });
+        }
+        return Ok(());
+    }
+
+    pub fn print_leaderboard(&self) {
+        let mut order: Vec<&LadderEntry> = self.entries.iter().collect();
+        order.sort_by(|a, b| b.rating.total_cmp(&a.rating));
+        println!(
+            "{:>4}  {:<32} {:>8} {:>7} {:>8}",
+            "rank", "agent", "rating", "games", "penalty"
+        );
+        for (rank, e) in order.iter().enumerate() {
+            let averaged = if e.games > 0 {
+                (e.total_penalty_points as f64) / (e.games as f64)
+            } else {
+                0.0
+            };
+            println!(
+                "{:>4}  {:<32} {:>8.1} {:>7} {:>8.2}",
+                rank + 1,
+                e.spec,
+                e.rating,
+                e.games,
+                averaged
+            );
+        }
+    }
+}
+
+// Playing the given number of games among the registered agents and updating the ratings.
+pub fn run_ladder(
+    ladder: &mut Ladder,
+    rules: &RuleSet,
+    num_games: usize,
+) -> Result<(), HeartsError> {
+    let num_players = rules.num_players;
+    if ladder.entries.len() < num_players {
+        return Err(HeartsError::WrongNumberOfAgents {
+            expected: num_players,
+            actual: ladder.entries.len(),
+        });
+    }
+
+    let mut rng = rand::thread_rng();
+    for game in 1..=num_games {
+        // The agents that have played the fewest games are chosen, ties broken at random.
+        let mut candidates: Vec<usize> = (0..ladder.entries.len()).collect();
+        candidates.shuffle(&mut rng);
+        candidates.sort_by_key(|i| ladder.entries[*i].games);
+        let mut lineup: Vec<usize> = candidates[..num_players].to_vec();
+        lineup.shuffle(&mut rng);
+
+        let mut agents: Vec<Box<dyn Agent>> = Vec::new();
+        for i in lineup.iter() {
+            agents.push(create_agent_from_spec(&ladder.entries[*i].spec)?);
+        }
+
+        let mut whole_card_sequence: [i32; NUM_CARDS] = [-1; NUM_CARDS];
+        let mut whole_agent_sequence: [i32; NUM_CARDS] = [-1; NUM_CARDS];
+        let mut extra_penalty_points: Vec<i32> = vec![0; num_players];
+        play_one_game(
+            &mut agents,
+            rules,
+            game,
+            rand::random(),
+            &mut whole_card_sequence,
+            &mut whole_agent_sequence,
+            &mut extra_penalty_points,
+        )?;
+        let mut penalty_points =
+            calc_penalty_points(&whole_card_sequence, &whole_agent_sequence, rules);
+        for i in 0..num_players {
+            penalty_points[i] += extra_penalty_points[i];
+        }
+
+        update_ratings(ladder, &lineup, &penalty_points, rules);
+    }
+
+    return Ok(());
+}
+
+fn update_ratings(ladder: &mut Ladder, lineup: &[usize], penalty_points: &[i32], rules: &RuleSet) {
+    let n = lineup.len();
+    let ratings: Vec<f64> = lineup.iter().map(|i| ladder.entries[*i].rating).collect();
+    let num_teams = rules.get_num_teams();
+    let team_points = rules.get_team_points(penalty_points);
+
+    // Number of opponents of each player.
+    let num_opponents = if rules.partnership { n - 2 } else { n - 1 };
+
+    for a in 0..n {
+        let mut delta = 0.0;
+        for b in 0..n {
+            if a == b || rules.get_partner(a) == Some(b) {
+                continue;
+            }
+            let score = match team_points[a % num_teams].cmp(&team_points[b % num_teams]) {
+                Ordering::Less => 1.0,
+                Ordering::Equal => 0.5,
+                Ordering::Greater => 0.0,
+            };
+            let expected = 1.0 / (1.0 + 10f64.powf((ratings[b] - ratings[a]) / 400.0));
+            delta += score - expected;
+        }
+
+        let entry = &mut ladder.entries[lineup[a]];
+        entry.rating += K_FACTOR / (num_opponents as f64) * delta;
+        entry.games += 1;
+        entry.total_penalty_points += penalty_points[a] as i64;
+    }
+}
+
+fn create_agent_from_spec(spec: &str) -> Result<Box<dyn Agent>, HeartsError> {
+    let (number, profile) = match spec.split_once(':') {
+        Some((number, path)) => (number, Some(path)),
+        None => (spec, None),
+    };
+    let number: i32 = number
+        .parse()
+        .map_err(|_| HeartsError::InvalidAgentSpec(spec.to_string()))?;
+    let params = match profile {
+        Some(path) if number == 2 => RuleBasedParams::load(Path::new(path))?,
+        Some(_) => return Err(HeartsError::InvalidAgentSpec(spec.to_string())),
+        None => RuleBasedParams::default(),
+    };
+    return create_agent(number, params, None);
+}
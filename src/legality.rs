@@ -0,0 +1,518 @@
+//
+// Legal moves
+//
+// The state of a game with all hands open, as kept by the game engine, a UI or the protocol layer,
+// and the queries on which cards a player may play in it and why the other cards may not be played.
+//
+
+use std::fmt;
+
+use crate::replay::replay_game;
+use crate::rules::RuleSet;
+use crate::{
+    breaks_heart, determine_winner, get_illegal_reason, validate_card, CARD_NAME, NUM_CARDS,
+};
+
+// Reason why a card in the hand may not be played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IllegalReason {
+    // The player has a card of the suit of the leading card.
+    MustFollowSuit,
+    // Hearts (and S-Q, depending on the rules) may not be led until hearts are broken.
+    HeartsNotBroken,
+    // The first trick must be led with the opening card (C-2 by default).
+    MustLeadOpeningCard,
+    // Penalty cards may not be played in the first trick unless the player has nothing else.
+    NoPointsOnFirstTrick,
+    // The player does not have the card.
+    NotInHand,
+    // It is not the turn of the player.
+    NotYourTurn,
+}
+
+impl fmt::Display for IllegalReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            IllegalReason::MustFollowSuit => "must follow suit",
+            IllegalReason::HeartsNotBroken => "hearts are not broken",
+            IllegalReason::MustLeadOpeningCard => "must lead the opening card",
+            IllegalReason::NoPointsOnFirstTrick => "no penalty points on the first trick",
+            IllegalReason::NotInHand => "the card is not in the hand",
+            IllegalReason::NotYourTurn => "it is not the turn of the player",
+        };
+        return write!(f, "{}", text);
+    }
+}
+
+// Error raised by the engine when an agent plays a card that is not allowed, if the rules abort the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameError {
+    // The card may not be played in the current trick for the reason.
+    IllegalMove {
+        seat: usize,
+        card: i32,
+        reason: IllegalReason,
+    },
+    // The card is not in the hand of the agent (or is not a card at all).
+    CardNotInHand {
+        seat: usize,
+        card: i32,
+    },
+    // All of the cards have already been played.
+    GameFinished,
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            GameError::IllegalMove { seat, card, reason } => write!(
+                f,
+                "Agent {} played {}, which is illegal: {}",
+                seat + 1,
+                CARD_NAME[*card as usize],
+                reason
+            ),
+            GameError::CardNotInHand { seat, card } => match CARD_NAME.get(*card as usize) {
+                Some(name) => write!(
+                    f,
+                    "Agent {} played {}, which is not in its hand",
+                    seat + 1,
+                    name
+                ),
+                None => write!(f, "Agent {} played an invalid card {}", seat + 1, card),
+            },
+            GameError::GameFinished => write!(f, "the game is already finished"),
+        };
+    }
+}
+
+impl std::error::Error for GameError {}
+
+// A card played without following suit although the player had a card of the suit of the leading card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Renege {
+    pub seat: usize,
+    pub trick: usize,
+    pub card: i32,
+}
+
+impl fmt::Display for Renege {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(
+            f,
+            "Agent {} reneged with {} in trick {}",
+            self.seat + 1,
+            CARD_NAME[self.card as usize],
+            self.trick + 1
+        );
+    }
+}
+
+// The hands are indexed by the seats and the played cards are replaced with -1.
+// The sequences are filled up to idx, and seat is the player who plays the next card.
+#[derive(Clone)]
+pub struct GameState {
+    pub hands: Vec<Vec<i32>>,
+    pub whole_card_sequence: [i32; NUM_CARDS],
+    pub whole_agent_sequence: [i32; NUM_CARDS],
+    pub idx: usize,
+    pub seat: usize,
+    pub bh_flag: bool,
+}
+
+impl GameState {
+    // The state at the beginning of the play; the holder of the opening card leads the first trick.
+    pub fn new(hands: Vec<Vec<i32>>, rules: &RuleSet) -> Self {
+        let opening_card = rules.get_opening_card();
+        let seat = hands
+            .iter()
+            .position(|hand| hand.contains(&opening_card))
+            .unwrap_or(0);
+        Self {
+            hands,
+            whole_card_sequence: [-1; NUM_CARDS],
+            whole_agent_sequence: [-1; NUM_CARDS],
+            idx: 0,
+            seat,
+            bh_flag: false,
+        }
+    }
+
+    pub fn get_trick(&self) -> usize {
+        return self.idx / self.hands.len();
+    }
+
+    // The cards played in the current trick so far, followed by -1.
+    pub fn get_card_sequence(&self) -> &[i32] {
+        let num_players = self.hands.len();
+        let trick = self.get_trick();
+        return &self.whole_card_sequence[(trick * num_players)..((trick + 1) * num_players)];
+    }
+
+    // Letting the player in turn play the card, and passing the turn to the next player
+    // (or to the winner of the trick when it is completed).
+    // The card must be in the hand of the player and legal, except for reneges if the rules allow them.
+    pub fn play(&mut self, card: i32, rules: &RuleSet) -> Result<(), GameError> {
+        let num_players = self.hands.len();
+        let trick = self.get_trick();
+        if self.is_finished() || self.idx >= NUM_CARDS {
+            return Err(GameError::GameFinished);
+        }
+        match validate_card(
+            &self.hands[self.seat],
+            self.get_card_sequence(),
+            card,
+            trick,
+            self.bh_flag,
+            self.seat,
+            rules,
+        ) {
+            Ok(()) => {}
+            Err(GameError::IllegalMove {
+                reason: IllegalReason::MustFollowSuit,
+                ..
+            }) if rules.allow_renege => {}
+            Err(e) => return Err(e),
+        }
+
+        for c in self.hands[self.seat].iter_mut() {
+            if *c == card {
+                *c = -1;
+                break;
+            }
+        }
+        self.whole_card_sequence[self.idx] = card;
+        self.whole_agent_sequence[self.idx] = self.seat as i32;
+        if breaks_heart(card, rules) {
+            self.bh_flag = true;
+        }
+        self.idx += 1;
+
+        if self.idx.is_multiple_of(num_players) {
+            let range = (trick * num_players)..((trick + 1) * num_players);
+            self.seat = determine_winner(
+                &self.whole_agent_sequence[range.clone()],
+                &self.whole_card_sequence[range],
+            ) as usize;
+        } else {
+            self.seat = (self.seat + 1) % num_players;
+        }
+        return Ok(());
+    }
+
+    // A game ends when all of the cards in the hands have been played.
+    pub fn is_finished(&self) -> bool {
+        return self.hands.iter().all(|hand| hand.iter().all(|c| *c == -1));
+    }
+}
+
+// Getting the cards in the hand of the player that may be played in the current trick;
+// there are none if it is not the turn of the player.
+pub fn legal_moves(state: &GameState, seat: usize, rules: &RuleSet) -> Vec<i32> {
+    return state.hands[seat]
+        .iter()
+        .copied()
+        .filter(|c| *c != -1 && why_illegal(state, seat, *c, rules).is_none())
+        .collect();
+}
+
+// Getting the reason why the player may not play the card in the current trick, or None if it is legal.
+pub fn why_illegal(
+    state: &GameState,
+    seat: usize,
+    card: i32,
+    rules: &RuleSet,
+) -> Option<IllegalReason> {
+    if seat != state.seat {
+        return Some(IllegalReason::NotYourTurn);
+    }
+    if card == -1 || !state.hands[seat].contains(&card) {
+        return Some(IllegalReason::NotInHand);
+    }
+    return get_illegal_reason(
+        &state.hands[seat],
+        state.get_card_sequence(),
+        card,
+        state.get_trick(),
+        state.bh_flag,
+        rules,
+    );
+}
+
+// Finding the reneges in a finished game from the dealt cards and the play history.
+pub fn detect_reneges(
+    dealt_cards: &[i32],
+    whole_card_sequence: &[i32; NUM_CARDS],
+    whole_agent_sequence: &[i32; NUM_CARDS],
+    rules: &RuleSet,
+) -> Vec<Renege> {
+    let num_players = rules.num_players;
+    let mut reneges: Vec<Renege> = Vec::new();
+    replay_game(
+        dealt_cards,
+        whole_card_sequence,
+        whole_agent_sequence,
+        rules,
+        |d| {
+            let card_sequence =
+                &d.whole_card_sequence[(d.trick * num_players)..((d.trick + 1) * num_players)];
+            let reason =
+                get_illegal_reason(d.hand, card_sequence, d.card, d.trick, d.bh_flag, rules);
+            if reason == Some(IllegalReason::MustFollowSuit) {
+                reneges.push(Renege {
+                    seat: d.seat,
+                    trick: d.trick,
+                    card: d.card,
+                });
+            }
+        },
+    );
+    return reneges;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(name: &str) -> i32 {
+        return CARD_NAME.iter().position(|n| *n == name).unwrap() as i32;
+    }
+
+    fn cards(names: &[&str]) -> Vec<i32> {
+        return names.iter().map(|n| card(n)).collect();
+    }
+
+    // A state of four players in the trick, where the led cards have been played from seat 0 on
+    // and the player at the seat after them is to play the given hand.
+    fn state_at(trick: usize, led: &[&str], hand: &[&str], bh_flag: bool) -> GameState {
+        let seat = led.len();
+        let mut hands: Vec<Vec<i32>> = vec![Vec::new(); 4];
+        hands[seat] = cards(hand);
+        let mut state = GameState {
+            hands,
+            whole_card_sequence: [-1; NUM_CARDS],
+            whole_agent_sequence: [-1; NUM_CARDS],
+            idx: trick * 4 + seat,
+            seat,
+            bh_flag,
+        };
+        for (i, c) in cards(led).iter().enumerate() {
+            state.whole_card_sequence[trick * 4 + i] = *c;
+            state.whole_agent_sequence[trick * 4 + i] = i as i32;
+        }
+        return state;
+    }
+
+    fn no_points_on_first_trick() -> RuleSet {
+        return RuleSet {
+            points_on_first_trick: false,
+            ..RuleSet::default()
+        };
+    }
+
+    #[test]
+    fn must_follow_suit() {
+        let rules = RuleSet::default();
+        let state = state_at(1, &["C-K"], &["C-5", "D-3"], false);
+        assert_eq!(
+            why_illegal(&state, 1, card("D-3"), &rules),
+            Some(IllegalReason::MustFollowSuit)
+        );
+        assert_eq!(legal_moves(&state, 1, &rules), cards(&["C-5"]));
+    }
+
+    #[test]
+    fn hearts_not_broken() {
+        let rules = RuleSet::default();
+        let state = state_at(1, &[], &["C-5", "H-3"], false);
+        assert_eq!(
+            why_illegal(&state, 0, card("H-3"), &rules),
+            Some(IllegalReason::HeartsNotBroken)
+        );
+        assert_eq!(legal_moves(&state, 0, &rules), cards(&["C-5"]));
+
+        let state = state_at(1, &[], &["C-5", "H-3"], true);
+        assert_eq!(legal_moves(&state, 0, &rules), cards(&["C-5", "H-3"]));
+    }
+
+    #[test]
+    fn must_lead_opening_card() {
+        let rules = RuleSet::default();
+        let state = state_at(0, &[], &["C-2", "C-5"], false);
+        assert_eq!(
+            why_illegal(&state, 0, card("C-5"), &rules),
+            Some(IllegalReason::MustLeadOpeningCard)
+        );
+        assert_eq!(legal_moves(&state, 0, &rules), cards(&["C-2"]));
+    }
+
+    #[test]
+    fn heart_as_opening_card() {
+        let rules = RuleSet {
+            opening_card: card("H-2"),
+            ..RuleSet::default()
+        };
+        let state = state_at(0, &[], &["C-5", "H-2", "H-9"], false);
+        assert_eq!(legal_moves(&state, 0, &rules), cards(&["H-2"]));
+
+        let rules = RuleSet {
+            opening_card: card("S-Q"),
+            queen_breaks_hearts: true,
+            ..RuleSet::default()
+        };
+        let state = state_at(0, &[], &["C-5", "S-Q"], false);
+        assert_eq!(legal_moves(&state, 0, &rules), cards(&["S-Q"]));
+    }
+
+    #[test]
+    fn no_points_on_first_trick_when_void() {
+        let rules = no_points_on_first_trick();
+        let state = state_at(0, &["C-2"], &["D-3", "H-4", "S-Q"], false);
+        assert_eq!(
+            why_illegal(&state, 1, card("H-4"), &rules),
+            Some(IllegalReason::NoPointsOnFirstTrick)
+        );
+        assert_eq!(
+            why_illegal(&state, 1, card("S-Q"), &rules),
+            Some(IllegalReason::NoPointsOnFirstTrick)
+        );
+        assert_eq!(legal_moves(&state, 1, &rules), cards(&["D-3"]));
+
+        // Penalty cards may be played by default.
+        let rules = RuleSet::default();
+        assert_eq!(
+            legal_moves(&state, 1, &rules),
+            cards(&["D-3", "H-4", "S-Q"])
+        );
+    }
+
+    #[test]
+    fn void_on_first_trick_with_only_penalty_cards() {
+        let rules = no_points_on_first_trick();
+        let state = state_at(0, &["C-2"], &["H-4", "S-Q"], false);
+        assert_eq!(legal_moves(&state, 1, &rules), cards(&["H-4", "S-Q"]));
+    }
+
+    #[test]
+    fn only_hearts_may_be_led_before_broken() {
+        let rules = RuleSet::default();
+        let state = state_at(1, &[], &["H-3", "H-9"], false);
+        assert_eq!(legal_moves(&state, 0, &rules), cards(&["H-3", "H-9"]));
+    }
+
+    #[test]
+    fn only_hearts_and_queen_of_spades() {
+        // S-Q is an ordinary spade by default, so it must be led while hearts are not broken.
+        let rules = RuleSet::default();
+        let state = state_at(1, &[], &["S-Q", "H-3"], false);
+        assert_eq!(legal_moves(&state, 0, &rules), cards(&["S-Q"]));
+    }
+
+    #[test]
+    fn card_not_in_hand() {
+        let rules = RuleSet::default();
+        let state = state_at(1, &["C-K"], &["C-5"], false);
+        assert_eq!(
+            why_illegal(&state, 1, card("C-9"), &rules),
+            Some(IllegalReason::NotInHand)
+        );
+        assert_eq!(
+            why_illegal(&state, 1, 99, &rules),
+            Some(IllegalReason::NotInHand)
+        );
+    }
+
+    // Playing a game in which card c is dealt to seat c % 4 and every player plays its first legal card,
+    // except that the first player who can fail to follow suit does so once.
+    // Returns the dealt cards, the final state, the renege and the number of legal discards by void players.
+    fn play_with_one_renege(rules: &RuleSet) -> (Vec<i32>, GameState, Renege, usize) {
+        let mut dealt_cards: Vec<i32> = Vec::new();
+        for seat in 0..4 {
+            dealt_cards.extend((seat..NUM_CARDS as i32).step_by(4));
+        }
+        let hands: Vec<Vec<i32>> = dealt_cards.chunks(13).map(|h| h.to_vec()).collect();
+        let mut state = GameState::new(hands, rules);
+
+        let mut renege: Option<Renege> = None;
+        let mut void_discards = 0;
+        while !state.is_finished() {
+            let seat = state.seat;
+            let led = state.get_card_sequence()[0];
+            let legal = legal_moves(&state, seat, rules);
+            let off_suit = state.hands[seat]
+                .iter()
+                .copied()
+                .find(|c| *c != -1 && led != -1 && c / 13 != led / 13);
+            let card = match off_suit {
+                Some(c) if renege.is_none() && !legal.contains(&c) => {
+                    renege = Some(Renege {
+                        seat,
+                        trick: state.get_trick(),
+                        card: c,
+                    });
+                    c
+                }
+                _ => legal[0],
+            };
+            if led != -1 && card / 13 != led / 13 && legal.contains(&card) {
+                void_discards += 1;
+            }
+            state.play(card, rules).unwrap();
+        }
+        return (dealt_cards, state, renege.unwrap(), void_discards);
+    }
+
+    #[test]
+    fn renege_is_detected() {
+        let rules = RuleSet {
+            allow_renege: true,
+            ..RuleSet::default()
+        };
+        let (dealt_cards, state, renege, void_discards) = play_with_one_renege(&rules);
+        assert_eq!(
+            renege,
+            Renege {
+                seat: 1,
+                trick: 0,
+                card: card("D-2"),
+            }
+        );
+        // Legal discards by void players are not reneges.
+        assert!(void_discards > 0);
+        assert_eq!(
+            detect_reneges(
+                &dealt_cards,
+                &state.whole_card_sequence,
+                &state.whole_agent_sequence,
+                &rules
+            ),
+            vec![renege]
+        );
+    }
+
+    #[test]
+    fn renege_is_rejected_unless_allowed() {
+        let rules = RuleSet::default();
+        let mut state = state_at(1, &["C-K"], &["C-5", "D-3"], false);
+        assert_eq!(
+            state.play(card("D-3"), &rules),
+            Err(GameError::IllegalMove {
+                seat: 1,
+                card: card("D-3"),
+                reason: IllegalReason::MustFollowSuit,
+            })
+        );
+    }
+
+    #[test]
+    fn not_your_turn() {
+        let rules = RuleSet::default();
+        let mut state = state_at(1, &["C-K"], &["C-5"], false);
+        state.hands[2] = cards(&["C-7"]);
+        assert_eq!(
+            why_illegal(&state, 2, card("C-7"), &rules),
+            Some(IllegalReason::NotYourTurn)
+        );
+        assert!(legal_moves(&state, 2, &rules).is_empty());
+    }
+}
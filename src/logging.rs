@@ -0,0 +1,50 @@
+//
+// Logging
+//
+// Diagnostics are logged with the log crate under the following targets, so that each can be turned on separately:
+// deal (the dealt hands and the passed cards), trick (the cards played in each trick and what the engine did
+// about timeouts, illegal moves and reneges) and agent (the decisions of the agents; the scores of the rule-based agent
+// at trace level). Other messages, such as the end of a match, are logged under the name of the program.
+// The filter is taken from RUST_LOG and then from --log (e.g. "trick=debug,agent=trace"), which takes precedence,
+// and only warnings are shown by default; the log goes to stderr, or to the file given by --log-file.
+//
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use env_logger::{Builder, Env, Target, WriteStyle};
+use log::LevelFilter;
+
+pub const DEAL: &str = "deal";
+pub const TRICK: &str = "trick";
+pub const AGENT: &str = "agent";
+
+// Setting up the logger; explain turns on the scores of the rule-based agents (see --explain).
+pub fn init(filter: Option<&str>, path: Option<&Path>, explain: bool) -> io::Result<()> {
+    let mut builder = Builder::new();
+    builder.filter_level(LevelFilter::Warn);
+    builder.parse_env(Env::default());
+    if explain {
+        builder.filter_module(AGENT, LevelFilter::Trace);
+    }
+    if let Some(filter) = filter {
+        builder.parse_filters(filter);
+    }
+    builder.format(|buf, record| {
+        writeln!(
+            buf,
+            "{:<5} {:<5} {}",
+            record.level(),
+            record.target(),
+            record.args()
+        )
+    });
+    if let Some(path) = path {
+        let file = File::create(path)?;
+        builder.target(Target::Pipe(Box::new(file)));
+        builder.write_style(WriteStyle::Never);
+    }
+    builder.try_init().map_err(io::Error::other)?;
+    return Ok(());
+}
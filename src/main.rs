@@ -1,5 +1,40 @@
+#![allow(
+    clippy::needless_return,
+    clippy::needless_range_loop,
+    clippy::too_many_arguments
+)]
+
+use std::time::{Duration, Instant};
+
+use log::{debug, info, trace};
 use rand::seq::SliceRandom;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+mod agents;
+mod analyze;
+mod clock;
+mod dataset;
+mod error;
+mod experiment;
+mod export;
+mod ladder;
+mod legality;
+mod logging;
+mod observation;
+mod record;
+mod replay;
+mod rules;
+mod train;
+mod tune;
+
+use agents::linear_q_agent::{load_weights, LinearQAgent};
+use agents::neural_network_agent::NeuralNetworkAgent;
+use clock::{Clock, TimeBudget};
+use error::HeartsError;
+use legality::{detect_reneges, GameError, IllegalReason};
+use rules::{HeartScoring, IllegalMovePolicy, MoonShot, Passing, RuleSet, TimeoutFallback};
 
 // Total number of games
 const NUM_GAMES: usize = 1;
@@ -10,8 +45,8 @@ const NUM_KC: usize = 13;
 // Number of all cards
 const NUM_CARDS: usize = NUM_KC * 4;
 
-// Number of players; HEARTS expects to be played by four players.
-const NUM_PLAYERS: usize = 4;
+// Maximum number of players; the number of players is given by the rule set (four by default).
+const MAX_PLAYERS: usize = 6;
 
 const CLUB: i32 = 0;
 const DIA: i32 = 1;
@@ -19,144 +54,666 @@ const SPADE: i32 = 2;
 const HEART: i32 = 3;
 
 const C_2: i32 = 0;
+const C_3: i32 = 1;
+const D_2: i32 = DIA * (NUM_KC as i32);
+const D_3: i32 = DIA * (NUM_KC as i32) + 1;
+const D_J: i32 = DIA * (NUM_KC as i32) + 9;
 const S_Q: i32 = SPADE * (NUM_KC as i32) + 10;
 const S_K: i32 = SPADE * (NUM_KC as i32) + 11;
 const S_A: i32 = SPADE * (NUM_KC as i32) + 12;
 
-const DEBUG_OUTPUT: bool = true;
+// Weights of the network used by the neural network agent.
+const MODEL_PATH: &str = "model.bin";
+
+// Weights of the linear Q-learning agent, written by the train command.
+const Q_WEIGHTS_PATH: &str = "q_weights.txt";
+
+// Ratings of the registered agents, updated by the ladder command.
+const LADDER_PATH: &str = "ladder.json";
+
+// Options followed by a value; the other arguments are the command and its arguments, or flags such as --explain.
+const OPTIONS_WITH_VALUE: [&str; 9] = [
+    "--rules",
+    "--profile",
+    "--log",
+    "--log-file",
+    "--save",
+    "--results",
+    "--summary",
+    "--games",
+    "--seed",
+];
 
 fn main() {
     // Assigning agents:
     // 1 -> Random agent; it plays cards from its hand at random.
     // 2 -> Rule-based agent; it plays cards based on the pre-determined rules.
-    let idx: [i32; NUM_PLAYERS] = [2, 2, 2, 2];
+    // 3 -> Neural network agent; it plays cards based on the network loaded from MODEL_PATH.
+    // 4 -> Linear Q-learning agent; it plays cards based on the weights loaded from Q_WEIGHTS_PATH.
+    // The first agents are used for the number of players given by the rule set.
+    let idx: [i32; MAX_PLAYERS] = [2, 2, 2, 2, 2, 2];
+
+    let args: Vec<String> = std::env::args().collect();
+
+    // Option: --rules <path>
+    // The rule variant is loaded from a TOML or JSON file (see rules.rs).
+    let rules = match args.iter().position(|a| a == "--rules") {
+        Some(i) => {
+            let path = std::path::Path::new(args.get(i + 1).map(|s| s.as_str()).unwrap_or(""));
+            match RuleSet::load(path) {
+                Ok(rules) => rules,
+                Err(e) => {
+                    eprintln!("Failed to load {}: {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => RuleSet::default(),
+    };
+
+    // Option: --profile <agent number>=<path>
+    // The constants of the rule-based agent at the given seat are loaded from a TOML or JSON file,
+    // so that differently tuned rule-based agents can play at the same table.
+    let mut profiles: [RuleBasedParams; MAX_PLAYERS] = [RuleBasedParams::default(); MAX_PLAYERS];
+    for i in 0..args.len() {
+        if args[i] != "--profile" {
+            continue;
+        }
+        let (seat, path) = match args.get(i + 1).and_then(|a| a.split_once('=')) {
+            Some((seat, path)) => (seat, path),
+            None => {
+                eprintln!("Usage: --profile <agent number>=<path>");
+                std::process::exit(1);
+            }
+        };
+        let seat: usize = match seat.parse() {
+            Ok(seat) if (1..=rules.num_players).contains(&seat) && idx[seat - 1] == 2 => seat,
+            _ => {
+                eprintln!("Agent {} is not a rule-based agent.", seat);
+                std::process::exit(1);
+            }
+        };
+        match RuleBasedParams::load(std::path::Path::new(path)) {
+            Ok(params) => profiles[seat - 1] = params,
+            Err(e) => {
+                eprintln!("Failed to load {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
 
-    // Making instances of four agents and store the objects in Vec.
-    let mut agents: Vec<Box<dyn Agent>> = Vec::new();
-    for i in 0..NUM_PLAYERS {
-        match idx[i] {
-            1 => agents.push(Box::new(RandomAgent::new())),
-            2 => agents.push(Box::new(RuleBasedAgent::new())),
-            _ => panic!("Specify correct agent number."),
+    // Option: --explain
+    // The rule-based agents log the score of every valid card and the rules that fired for each decision.
+    let explain = args.iter().any(|a| a == "--explain");
+
+    // Option: --log <filter>, --log-file <path>
+    // The diagnostics of the given targets are logged to stderr or to the file (see logging.rs).
+    let get_arg = |option: &str| {
+        args.iter()
+            .position(|a| a == option)
+            .and_then(|i| args.get(i + 1))
+            .map(|s| s.as_str())
+    };
+    let log_path = get_arg("--log-file").map(std::path::Path::new);
+    if let Err(e) = logging::init(get_arg("--log"), log_path, explain) {
+        eprintln!("Failed to set up the logger: {}", e);
+        std::process::exit(1);
+    }
+
+    // Making instances of the agents and store the objects in Vec;
+    // they are made only for the commands that play with the agents given by idx.
+    let create_agents = || {
+        let mut agents: Vec<Box<dyn Agent>> = Vec::new();
+        for i in 0..rules.num_players {
+            match create_agent(idx[i], profiles[i], None) {
+                Ok(agent) => agents.push(agent),
+                Err(e) => {
+                    eprintln!("Failed to create Agent {}: {}", i + 1, e);
+                    std::process::exit(1);
+                }
+            }
         }
+        return agents;
+    };
+
+    // The command and its arguments are the arguments other than the options and their values,
+    // so that the options can be given before or after the command.
+    let mut positional: Vec<&str> = Vec::new();
+    let mut i = 1;
+    while i < args.len() {
+        if OPTIONS_WITH_VALUE.contains(&args[i].as_str()) {
+            i += 2;
+            continue;
+        }
+        if !args[i].starts_with("--") {
+            positional.push(&args[i]);
+        }
+        i += 1;
+    }
+    let command = positional.first().copied();
+    let get_positional = |i: usize| positional.get(i).copied();
+
+    // Usage: hearts_rs dataset <number of games> <output directory>
+    // Letting the agents play the given number of games and exporting every decision for supervised learning.
+    if command == Some("dataset") {
+        let num_games: usize = get_positional(1)
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(NUM_GAMES);
+        let out_dir = std::path::PathBuf::from(get_positional(2).unwrap_or("dataset"));
+        let mut agents = create_agents();
+        if let Err(e) = dataset::export_dataset(&mut agents, &rules, num_games, &out_dir) {
+            eprintln!("Failed to export the dataset: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Usage: hearts_rs train <number of games>
+    // Training the linear Q-learning agent by self-play and saving its weights to Q_WEIGHTS_PATH.
+    if command == Some("train") {
+        let num_games: usize = get_positional(1)
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(NUM_GAMES);
+        if let Err(e) = train::train(&rules, num_games, std::path::Path::new(Q_WEIGHTS_PATH)) {
+            eprintln!("Failed to train the agent: {}", e);
+            std::process::exit(1);
+        }
+        return;
     }
 
-    let mut total_penalty_points: [i32; NUM_PLAYERS] = [0; NUM_PLAYERS];
+    // Usage: hearts_rs tune <number of generations> <number of games per evaluation>
+    // Tuning the constants of the rule-based agent by a genetic algorithm and printing the best ones.
+    if command == Some("tune") {
+        let num_generations: usize = get_positional(1).and_then(|s| s.parse().ok()).unwrap_or(10);
+        let num_games: usize = get_positional(2)
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(NUM_GAMES);
+        tune::tune(&rules, num_generations, num_games);
+        return;
+    }
 
-    // Letting agents play the card game "Hearts" NUM_GAMES times.
-    for _ in 1..=NUM_GAMES {
+    // Usage: hearts_rs ladder <number of games> [<agent spec>...]
+    // Registering the given agents (e.g. "1" or "2:profiles/default.toml") in the ladder saved in LADDER_PATH,
+    // letting the registered agents play the given number of games, and printing the leaderboard.
+    if command == Some("ladder") {
+        let num_games: usize = get_positional(1)
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(NUM_GAMES);
+        let path = std::path::Path::new(LADDER_PATH);
+        let result = ladder::Ladder::load(path).and_then(|mut ladder| {
+            for spec in positional.iter().skip(2) {
+                ladder.register(spec)?;
+            }
+            ladder::run_ladder(&mut ladder, &rules, num_games)?;
+            ladder.save(path)?;
+            ladder.print_leaderboard();
+            return Ok(());
+        });
+        if let Err(e) = result {
+            eprintln!("Failed to run the ladder: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Usage: hearts_rs run <experiment file>
+    // Running the experiment declared in the TOML, YAML or JSON file (see experiment.rs)
+    // and writing its results tagged with the hash of the experiment and the version of the program.
+    if command == Some("run") {
+        let path = std::path::Path::new(get_positional(1).unwrap_or(""));
+        let result = experiment::Experiment::load(path)
+            .and_then(|experiment| experiment::run_experiment(&experiment));
+        match result {
+            Ok(out_path) => println!("Results were written to {}", out_path.display()),
+            Err(e) => {
+                eprintln!("Failed to run {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // Usage: hearts_rs analyze <saved game> [--seed <number>]
+    // Replaying a game saved with --save and reporting the blunders of each agent trick by trick;
+    // the rollouts are drawn from the seed (0 by default).
+    if command == Some("analyze") {
+        let path = std::path::Path::new(get_positional(1).unwrap_or(""));
+        let seed: u64 = match get_arg("--seed").map(|s| s.parse()) {
+            Some(Ok(seed)) => seed,
+            Some(Err(_)) => {
+                eprintln!("Usage: --seed <number>");
+                std::process::exit(1);
+            }
+            None => 0,
+        };
+        match record::GameRecord::load(path) {
+            Ok(record) => {
+                if let Err(e) = analyze::analyze(&record, seed) {
+                    eprintln!("Failed to analyze {}: {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to load {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(command) = command {
+        eprintln!("Unknown command: {}", command);
+        std::process::exit(1);
+    }
+    let mut agents = create_agents();
+
+    // Option: --save <directory>
+    // Each game is saved to the directory as game_<number>.json, which can be analyzed afterwards.
+    let save_dir = args
+        .iter()
+        .position(|a| a == "--save")
+        .and_then(|i| args.get(i + 1))
+        .map(std::path::PathBuf::from);
+    if let Some(dir) = &save_dir {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            eprintln!("Failed to create {}: {}", dir.display(), e);
+            std::process::exit(1);
+        }
+    }
+
+    // Option: --games <number>
+    // The number of games played (NUM_GAMES by default); with an end score in the rule set,
+    // enough games must be played for matches to end.
+    let num_games: usize = match get_arg("--games").map(|s| s.parse()) {
+        Some(Ok(num_games)) => num_games,
+        Some(Err(_)) => {
+            eprintln!("Usage: --games <number>");
+            std::process::exit(1);
+        }
+        None => NUM_GAMES,
+    };
+
+    // Option: --results <path>, --summary <path>
+    // The results of each game and the summary of all of the games are exported as CSV or JSON (see export.rs).
+    let results_path = get_arg("--results").map(std::path::PathBuf::from);
+    let summary_path = get_arg("--summary").map(std::path::PathBuf::from);
+
+    let num_players = rules.num_players;
+    let seats = &idx[..num_players];
+    let mut games: Vec<export::GameSummary> = Vec::new();
+
+    // In partnership mode, the penalty points of partners are combined into those of their team.
+    let num_teams = rules.get_num_teams();
+
+    // When the rule set has an end score, games are grouped into matches;
+    // the team (or player) with the fewest penalty points when a match ends wins the match.
+    let mut match_points: Vec<i32> = vec![0; num_teams];
+    let mut match_wins: Vec<i32> = vec![0; num_teams];
+    let mut game_in_match = 0;
+
+    // Letting agents play the card game "Hearts" the given number of times.
+    for game in 1..=num_games {
         let mut whole_card_sequence: [i32; NUM_CARDS] = [-1; NUM_CARDS];
         let mut whole_agent_sequence: [i32; NUM_CARDS] = [-1; NUM_CARDS];
 
-        play_one_game(
+        // The passing schedule starts over in each match.
+        game_in_match += 1;
+        let seed: u64 = rand::random();
+        let mut extra_penalty_points: Vec<i32> = vec![0; num_players];
+        let dealt_cards = match play_one_game(
             &mut agents,
+            &rules,
+            game_in_match,
+            seed,
             &mut whole_card_sequence,
             &mut whole_agent_sequence,
-        );
+            &mut extra_penalty_points,
+        ) {
+            Ok(dealt_cards) => dealt_cards,
+            Err(e) => {
+                eprintln!("Game {} was aborted: {}", game, e);
+                std::process::exit(1);
+            }
+        };
 
-        let penalty_points = calc_penalty_points(&whole_card_sequence, &whole_agent_sequence);
+        if let Some(dir) = &save_dir {
+            let path = dir.join(format!("game_{}.json", game));
+            let record = record::GameRecord::new(
+                &dealt_cards,
+                &whole_card_sequence,
+                &whole_agent_sequence,
+                &rules,
+            );
+            if let Err(e) = record.save(&path) {
+                eprintln!("Failed to save {}: {}", path.display(), e);
+            }
+        }
+
+        let mut penalty_points =
+            calc_penalty_points(&whole_card_sequence, &whole_agent_sequence, &rules);
+        for i in 0..num_players {
+            penalty_points[i] += extra_penalty_points[i];
+        }
 
-        for i in 0..NUM_PLAYERS {
-            total_penalty_points[i] += penalty_points[i];
+        let team_points = rules.get_team_points(&penalty_points);
+        for t in 0..num_teams {
+            match_points[t] += team_points[t];
+        }
+        games.push(export::GameSummary::new(
+            game,
+            seed,
+            seats,
+            &whole_card_sequence,
+            &whole_agent_sequence,
+            &penalty_points,
+            &rules,
+        ));
+
+        if rules.end_score > 0 && match_points.iter().any(|p| *p >= rules.end_score) {
+            let min = *match_points.iter().min().unwrap();
+            for t in 0..num_teams {
+                if match_points[t] == min {
+                    match_wins[t] += 1;
+                }
+            }
+            info!("Match ended: {:?}", match_points);
+            for agent in agents.iter_mut() {
+                agent.on_match_end(&match_points);
+            }
+            match_points = vec![0; num_teams];
+            game_in_match = 0;
         }
     }
 
-    let mut averaged_penalty_points: [f32; NUM_PLAYERS] = [0.0; NUM_PLAYERS];
-    let mut averaged_penalty_ratio: [f32; NUM_PLAYERS] = [0.0; NUM_PLAYERS];
+    let summary = export::Summary::new(&games, seats, &match_wins, &rules);
+    println!("{:?}", summary.averaged_penalty_points);
+    println!("{:?}", summary.averaged_penalty_ratio);
+    if let Some(averaged_team_points) = &summary.averaged_team_points {
+        // Team t consists of agent t + 1 and its partner.
+        println!("{:?}", averaged_team_points);
+    }
+    if let Some(match_wins) = &summary.match_wins {
+        println!("{:?}", match_wins);
+    }
 
-    for i in 0..NUM_PLAYERS {
-        averaged_penalty_points[i] = (total_penalty_points[i] as f32) / (NUM_GAMES as f32);
+    if let Some(path) = &results_path {
+        if let Err(e) = export::export_games(path, &games, &rules) {
+            eprintln!("Failed to export {}: {}", path.display(), e);
+        }
     }
-    let sum: f32 = averaged_penalty_points.iter().sum();
-    for i in 0..NUM_PLAYERS {
-        averaged_penalty_ratio[i] = averaged_penalty_points[i] / sum;
+    if let Some(path) = &summary_path {
+        if let Err(e) = export::export_summary(path, &summary, &rules) {
+            eprintln!("Failed to export {}: {}", path.display(), e);
+        }
     }
+}
+
+// Making an instance of the agent with the given number (see idx in main).
+// The network or the weights of agents 3 and 4 are loaded from the model path if it is given,
+// and from MODEL_PATH or Q_WEIGHTS_PATH otherwise.
+fn create_agent(
+    number: i32,
+    params: RuleBasedParams,
+    model: Option<&std::path::Path>,
+) -> Result<Box<dyn Agent>, HeartsError> {
+    let load_error = |path: &std::path::Path, e: std::io::Error| {
+        std::io::Error::new(
+            e.kind(),
+            format!("failed to load {}: {}", path.display(), e),
+        )
+    };
+    return match number {
+        1 => Ok(Box::new(RandomAgent::new())),
+        2 => Ok(Box::new(RuleBasedAgent::with_params(params))),
+        3 => {
+            let path = model.unwrap_or(std::path::Path::new(MODEL_PATH));
+            let agent = NeuralNetworkAgent::load(path).map_err(|e| load_error(path, e))?;
+            Ok(Box::new(agent))
+        }
+        4 => {
+            let path = model.unwrap_or(std::path::Path::new(Q_WEIGHTS_PATH));
+            let weights = load_weights(path).map_err(|e| load_error(path, e))?;
+            Ok(Box::new(LinearQAgent::new(weights, 0.0)))
+        }
+        _ => Err(HeartsError::UnknownAgent(number)),
+    };
+}
 
-    println!("{:?}", averaged_penalty_points);
-    println!("{:?}", averaged_penalty_ratio);
+// Loading a configuration from a TOML, YAML or JSON file;
+// the format is determined by the extension of the file, .json for JSON, .yaml or .yml for YAML and TOML otherwise.
+fn load_config<T: serde::de::DeserializeOwned>(path: &std::path::Path) -> std::io::Result<T> {
+    let text = std::fs::read_to_string(path)?;
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    let result = match extension {
+        "json" => serde_json::from_str(&text).map_err(|e| e.to_string()),
+        "yaml" | "yml" => serde_yaml::from_str(&text).map_err(|e| e.to_string()),
+        _ => toml::from_str(&text).map_err(|e| e.to_string()),
+    };
+    return result.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e));
 }
 
+// The game is the number of the game (counted from 1), which determines the passing direction.
+// Penalty points given to the agents for illegal moves, depending on the rules, are added to extra_penalty_points.
+// All random choices in the game, including those of the agents that accept a seed, are made from the seed,
+// so that the game can be reproduced.
 fn play_one_game(
-    agents: &mut Vec<Box<dyn Agent>>,
+    agents: &mut [Box<dyn Agent>],
+    rules: &RuleSet,
+    game: usize,
+    seed: u64,
     whole_card_sequence: &mut [i32; NUM_CARDS],
     whole_agent_sequence: &mut [i32; NUM_CARDS],
-) {
-    // Cards are dealt to the four agents so that each has NUM_KC cards at the beginning of a game.
-    let dealt_cards = deal_cards(agents);
+    extra_penalty_points: &mut [i32],
+) -> Result<Vec<i32>, HeartsError> {
+    let num_players = rules.num_players;
+    let hand_size = rules.get_hand_size();
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+    for i in 0..num_players {
+        agents[i].on_game_start(i, rules);
+        agents[i].set_partner(rules.get_partner(i));
+        agents[i].set_seed(rng.gen());
+    }
+
+    // Cards are dealt to the agents so that each has the same number of cards at the beginning of a game.
+    let mut dealt_cards = deal_cards(agents, rules, &mut rng)?;
+
+    // Before playing, each agent passes cards to another agent.
+    let passing = rules.get_passing(game);
+    if passing != Passing::Hold {
+        dealt_cards = pass_cards(agents, passing, rules.num_passed_cards)?;
+    }
 
     // Getting the playing sequence in the first trick based on agents' hands.
-    // (the agent who has C-2 is the leading player in the initial trick).
-    let idx = dealt_cards.iter().position(|val| *val == C_2).unwrap_or(0);
-    let mut winner = (idx as i32) / (NUM_KC as i32);
+    // (the agent who has the opening card, C-2 by default, is the leading player in the initial trick).
+    let opening_card = rules.get_opening_card();
+    let idx = dealt_cards
+        .iter()
+        .position(|val| *val == opening_card)
+        .unwrap_or(0);
+    let mut winner = (idx / hand_size) as i32;
 
     // initializing the flag of "breaking heart"".
     let mut bh_flag = false;
 
-    // When each of the four players has played a card, it is called a "trick";
+    let mut clock = Clock::new(rules);
+
+    // When each of the players has played a card, it is called a "trick";
     // each player plays a card once in a trick.
-    for trick in 0..NUM_KC {
-        if DEBUG_OUTPUT {
-            println!("== trick {} ==", trick + 1);
-        }
+    for trick in 0..hand_size {
+        debug!(target: logging::TRICK, "== trick {} ==", trick + 1);
 
-        let agent_order = determine_agent_order(winner);
+        let agent_order = determine_agent_order(winner, num_players);
 
-        let mut card_sequence: [i32; NUM_PLAYERS] = [-1; NUM_PLAYERS];
+        let mut card_sequence: Vec<i32> = vec![-1; num_players];
 
-        for turn in 0..NUM_PLAYERS {
+        for turn in 0..num_players {
             let playing_agent = agent_order[turn] as usize;
 
-            if DEBUG_OUTPUT {
-                print_hand(&agents[playing_agent].get_hand(), playing_agent);
+            trace!(
+                target: logging::TRICK,
+                "{}",
+                format_hand(agents[playing_agent].get_hand(), playing_agent)
+            );
+
+            // Letting the agent choose a card within its time limit.
+            // The fallback card is played if the agent runs out of time, and the agent is not asked if it has no time left.
+            let budget = clock.get_budget(playing_agent);
+            let start = Instant::now();
+            let mut card = if budget.move_limit != Some(Duration::ZERO) {
+                agents[playing_agent].select_card(
+                    whole_card_sequence,
+                    whole_agent_sequence,
+                    trick,
+                    turn,
+                    bh_flag,
+                    rules,
+                    budget,
+                )
+            } else {
+                -1
+            };
+            let elapsed = start.elapsed();
+            debug!(
+                target: logging::AGENT,
+                "Agent {} selects {} in {:?}",
+                playing_agent + 1,
+                CARD_NAME.get(card as usize).unwrap_or(&"--"),
+                elapsed
+            );
+            if !clock.charge(playing_agent, elapsed) {
+                card = select_fallback_card(
+                    agents[playing_agent].get_hand(),
+                    whole_card_sequence,
+                    whole_agent_sequence,
+                    trick,
+                    turn,
+                    bh_flag,
+                    playing_agent,
+                    rules,
+                    &mut rng,
+                )?;
+                info!(
+                    target: logging::TRICK,
+                    "Agent {} ran out of time; {} is played instead.",
+                    playing_agent + 1,
+                    CARD_NAME.get(card as usize).unwrap_or(&"--")
+                );
             }
 
-            // Letting the agent choose a card.
-            let card = agents[playing_agent].select_card(
-                &whole_card_sequence,
-                &whole_agent_sequence,
+            // The card is not trusted; an illegal move is handled as the rules specify.
+            let hand = agents[playing_agent].get_hand();
+            match validate_card(
+                hand,
+                &card_sequence,
+                card,
                 trick,
-                turn,
                 bh_flag,
-            );
+                playing_agent,
+                rules,
+            ) {
+                Ok(()) => {}
+                // If reneging is allowed, failing to follow suit is accepted here and detected after the game.
+                Err(GameError::IllegalMove {
+                    reason: IllegalReason::MustFollowSuit,
+                    ..
+                }) if rules.allow_renege => {}
+                Err(e) => {
+                    if rules.illegal_move == IllegalMovePolicy::Abort {
+                        return Err(e.into());
+                    }
+                    if rules.illegal_move == IllegalMovePolicy::Penalize {
+                        extra_penalty_points[playing_agent] += rules.illegal_move_points;
+                    }
+                    let valid_cards = get_valid_cards(hand, &card_sequence, trick, bh_flag, rules);
+                    card = match valid_cards.choose(&mut rng) {
+                        Some(card) => *card,
+                        None => return Err(e.into()),
+                    };
+                    info!(
+                        target: logging::TRICK,
+                        "{}; {} is played instead.",
+                        e,
+                        CARD_NAME[card as usize]
+                    );
+                }
+            }
             agents[playing_agent].update_hand(card);
 
             card_sequence[turn] = card;
 
-            let idx = trick * NUM_PLAYERS + turn;
+            let idx = trick * num_players + turn;
             whole_card_sequence[idx] = card;
             whole_agent_sequence[idx] = playing_agent as i32;
 
-            // When a heart is played for the first time in a game, setting the flag to true.
-            if !bh_flag && get_suit(card) == HEART {
+            // When a heart (or S-Q, depending on the rules) is played for the first time in a game,
+            // setting the flag to true.
+            if !bh_flag && breaks_heart(card, rules) {
                 bh_flag = true;
             }
         }
 
         // The winner of the current trick becomes the leading player of the next trick.
         winner = determine_winner(&agent_order, &card_sequence);
+        for agent in agents.iter_mut() {
+            agent.on_trick_completed(trick, &card_sequence, &agent_order, winner as usize);
+        }
 
-        if DEBUG_OUTPUT {
-            println!("");
-            for (agent, card) in agent_order.iter().zip(card_sequence.iter()) {
-                print!("Agent {}: ", agent + 1);
-                print_card(*card);
-            }
-            println!("");
+        let played: Vec<String> = agent_order
+            .iter()
+            .zip(card_sequence.iter())
+            .map(|(agent, card)| format!("Agent {}: {}", agent + 1, CARD_NAME[*card as usize]))
+            .collect();
+        debug!(target: logging::TRICK, "{}", played.join(", "));
+    }
+
+    // Reneges are found from the play history after the game, as players at a table would do.
+    if rules.allow_renege {
+        for renege in detect_reneges(
+            &dealt_cards,
+            whole_card_sequence,
+            whole_agent_sequence,
+            rules,
+        ) {
+            extra_penalty_points[renege.seat] += rules.renege_points;
+            info!(target: logging::TRICK, "{}", renege);
         }
     }
 
-    // A single game ends when NUM_KC tricks have been carried out.
+    // Letting the agents know the result of the game.
+    let mut penalty_points = calc_penalty_points(whole_card_sequence, whole_agent_sequence, rules);
+    for i in 0..num_players {
+        penalty_points[i] += extra_penalty_points[i];
+    }
+    for agent in agents.iter_mut() {
+        agent.on_game_end(&penalty_points);
+    }
+
+    // A single game ends when all of the cards in the hands have been played.
+    // The hands at the beginning of the play (after passing) are returned so that the game can be replayed afterwards.
+    return Ok(dealt_cards);
 }
 
-fn deal_cards(agents: &mut Vec<Box<dyn Agent>>) -> Vec<i32> {
-    let mut v: Vec<i32> = (0..NUM_CARDS as i32).collect();
+fn deal_cards<R: Rng>(
+    agents: &mut [Box<dyn Agent>],
+    rules: &RuleSet,
+    rng: &mut R,
+) -> Result<Vec<i32>, HeartsError> {
+    if agents.len() != rules.num_players {
+        return Err(HeartsError::WrongNumberOfAgents {
+            expected: rules.num_players,
+            actual: agents.len(),
+        });
+    }
+
+    let hand_size = rules.get_hand_size();
+    let mut v: Vec<i32> = rules.get_deck();
     loop {
-        let mut rng = rand::thread_rng();
-        v.shuffle(&mut rng);
+        v.shuffle(rng);
 
         // Prohibiting hearts from appearing 13 times in a row.
         let mut count = 0;
-        for i in 0..NUM_CARDS {
+        for i in 0..v.len() {
             if get_suit(v[i]) == HEART {
                 count += 1;
             } else {
@@ -168,29 +725,89 @@ fn deal_cards(agents: &mut Vec<Box<dyn Agent>>) -> Vec<i32> {
         }
     }
 
-    for i in 0..NUM_PLAYERS {
-        let cards = &v[(i * NUM_KC)..((i + 1) * NUM_KC)];
-        agents[i].set_hand(&cards);
+    for i in 0..rules.num_players {
+        let cards = &v[(i * hand_size)..((i + 1) * hand_size)];
+        agents[i].set_hand(cards)?;
+        debug!(target: logging::DEAL, "{}", format_hand(cards, i));
+    }
+
+    return Ok(v);
+}
+
+// Letting each agent pass cards in the given direction, and returning the new hands in the order of the seats.
+// With an odd number of players, passing across is the same as passing left.
+// Each agent must pass the given number of different cards from its hand.
+fn pass_cards(
+    agents: &mut [Box<dyn Agent>],
+    passing: Passing,
+    num_cards: usize,
+) -> Result<Vec<i32>, HeartsError> {
+    let num_players = agents.len();
+    let offset = match passing {
+        Passing::Left => 1,
+        Passing::Right => num_players - 1,
+        Passing::Across if num_players.is_multiple_of(2) => num_players / 2,
+        Passing::Across => 1,
+        Passing::Hold => 0,
+    };
+
+    let mut passed_cards: Vec<Vec<i32>> = Vec::new();
+    for i in 0..num_players {
+        let mut cards = agents[i].select_passed_cards(num_cards);
+        cards.sort();
+        cards.dedup();
+        if cards.len() != num_cards
+            || cards
+                .iter()
+                .any(|c| *c == -1 || !agents[i].get_hand().contains(c))
+        {
+            return Err(HeartsError::InvalidPass { seat: i });
+        }
+        passed_cards.push(cards);
+        debug!(
+            target: logging::DEAL,
+            "Agent {} passes: {}",
+            i + 1,
+            format_cards(&passed_cards[i])
+        );
+    }
+
+    let mut hands: Vec<i32> = Vec::new();
+    for i in 0..num_players {
+        let from = (i + num_players - offset) % num_players;
+        let mut hand: Vec<i32> = agents[i]
+            .get_hand()
+            .iter()
+            .copied()
+            .filter(|c| !passed_cards[i].contains(c))
+            .collect();
+        hand.extend(passed_cards[from].iter());
+        hands.extend(hand.iter());
+    }
+
+    let hand_size = hands.len() / num_players;
+    for i in 0..num_players {
+        agents[i].set_hand(&hands[(i * hand_size)..((i + 1) * hand_size)])?;
     }
 
-    return v;
+    return Ok(hands);
 }
 
-fn determine_agent_order(winner: i32) -> [i32; NUM_PLAYERS] {
-    let mut order: [i32; NUM_PLAYERS] = [-1; NUM_PLAYERS];
+fn determine_agent_order(winner: i32, num_players: usize) -> Vec<i32> {
+    let mut order: Vec<i32> = vec![-1; num_players];
 
-    for i in 0..NUM_PLAYERS {
-        if winner + (i as i32) < (NUM_PLAYERS as i32) {
+    for i in 0..num_players {
+        if winner + (i as i32) < (num_players as i32) {
             order[i] = winner + (i as i32);
         } else {
-            order[i] = winner + (i as i32) - (NUM_PLAYERS as i32);
+            order[i] = winner + (i as i32) - (num_players as i32);
         }
     }
 
     return order;
 }
 
-fn determine_winner(agent_order: &[i32; NUM_PLAYERS], card_sequence: &[i32; NUM_PLAYERS]) -> i32 {
+fn determine_winner(agent_order: &[i32], card_sequence: &[i32]) -> i32 {
     let mut leading_card = card_sequence[0];
     let lc_suit = get_suit(leading_card);
     let mut winner = agent_order[0];
@@ -210,37 +827,127 @@ fn determine_winner(agent_order: &[i32; NUM_PLAYERS], card_sequence: &[i32; NUM_
 fn calc_penalty_points(
     card_sequence: &[i32; NUM_CARDS],
     agent_sequence: &[i32; NUM_CARDS],
-) -> [i32; NUM_PLAYERS] {
-    let mut penalty_points: [i32; NUM_PLAYERS] = [0; NUM_PLAYERS];
-    let mut card_subsequence: [i32; NUM_PLAYERS] = [-1; NUM_PLAYERS];
-    let mut agent_subsequence: [i32; NUM_PLAYERS] = [-1; NUM_PLAYERS];
-
-    for trick in 0..NUM_KC {
-        for turn in 0..NUM_PLAYERS {
-            let idx = trick * NUM_PLAYERS + turn;
-            card_subsequence[turn] = card_sequence[idx];
-            agent_subsequence[turn] = agent_sequence[idx];
-        }
-
-        // Each heart equals a one-point penalty and the S-Q equals a 13-point penalty,
-        // so the total number of penalty points is 26.
+    rules: &RuleSet,
+) -> Vec<i32> {
+    let num_players = rules.num_players;
+    let mut penalty_points: Vec<i32> = vec![0; num_players];
+    // Points of D-J, which are kept apart since they do not count for shooting the moon.
+    let mut jack_points: Vec<i32> = vec![0; num_players];
+
+    for trick in 0..rules.get_hand_size() {
+        let card_subsequence = &card_sequence[(trick * num_players)..((trick + 1) * num_players)];
+        let agent_subsequence = &agent_sequence[(trick * num_players)..((trick + 1) * num_players)];
+
+        // By default, each heart equals a one-point penalty and the S-Q equals a 13-point penalty,
+        // so the total number of penalty points is 26; D-J may also have points (see rules.rs).
         // The winner of a trick receives all of the penalty points of the cards played in the trick.
 
-        let winner = determine_winner(&agent_subsequence, &card_subsequence) as usize;
+        let winner = determine_winner(agent_subsequence, card_subsequence) as usize;
 
         for card in card_subsequence {
-            if card >= HEART * (NUM_KC as i32) {
-                penalty_points[winner] += 1;
-            } else if card == S_Q {
-                penalty_points[winner] += 13;
+            if *card == D_J {
+                jack_points[winner] += get_penalty_point(*card, rules);
             } else {
+                penalty_points[winner] += get_penalty_point(*card, rules);
             }
         }
     }
 
+    // When a player acquires all of the penalty points, the player "shoots the moon".
+    let total = rules.get_total_penalty_points();
+    if let Some(shooter) = get_moon_shooter(card_sequence, agent_sequence, rules) {
+        match rules.moon_shot {
+            MoonShot::Disabled => {}
+            MoonShot::AddToOthers => {
+                for i in 0..num_players {
+                    penalty_points[i] = if i == shooter { 0 } else { total };
+                }
+            }
+            MoonShot::SubtractFromSelf => {
+                penalty_points[shooter] = -total;
+            }
+        }
+    }
+
+    for i in 0..num_players {
+        penalty_points[i] += jack_points[i];
+    }
+
     return penalty_points;
 }
 
+// Getting the player who acquired all of the penalty points (except D-J) in a finished game, if any;
+// the player is also required to take D-J if the rules say so.
+fn get_moon_shooter(
+    card_sequence: &[i32; NUM_CARDS],
+    agent_sequence: &[i32; NUM_CARDS],
+    rules: &RuleSet,
+) -> Option<usize> {
+    let mut points: Vec<i32> = vec![0; rules.num_players];
+    for card in rules.get_deck() {
+        if card == D_J {
+            continue;
+        }
+        if let Some(taker) = get_card_taker(card, card_sequence, agent_sequence, rules) {
+            points[taker] += get_penalty_point(card, rules);
+        }
+    }
+    let total = rules.get_total_penalty_points();
+    return points
+        .iter()
+        .position(|p| *p == total && total > 0)
+        .filter(|s| {
+            !rules.moon_requires_jack
+                || get_card_taker(D_J, card_sequence, agent_sequence, rules) == Some(*s)
+        });
+}
+
+// Getting the player who won the trick in which the card was played, or None if it has not been played.
+fn get_card_taker(
+    card: i32,
+    card_sequence: &[i32; NUM_CARDS],
+    agent_sequence: &[i32; NUM_CARDS],
+    rules: &RuleSet,
+) -> Option<usize> {
+    let num_players = rules.num_players;
+    let trick = card_sequence.iter().position(|c| *c == card)? / num_players;
+    let range = (trick * num_players)..((trick + 1) * num_players);
+    if card_sequence[range.end - 1] == -1 {
+        return None;
+    }
+    return Some(determine_winner(&agent_sequence[range.clone()], &card_sequence[range]) as usize);
+}
+
+fn get_penalty_point(card: i32, rules: &RuleSet) -> i32 {
+    if get_suit(card) == HEART {
+        // The rank of the card is 0 for 2 and 12 for A.
+        let rank = card % (NUM_KC as i32);
+        return match rules.heart_scoring {
+            HeartScoring::Flat => rules.heart_points,
+            HeartScoring::Spot => rank + 2,
+            HeartScoring::SpotFaceTen if (9..12).contains(&rank) => 10,
+            HeartScoring::SpotFaceTen => rank + 2,
+        };
+    } else if card == S_Q {
+        return rules.queen_points;
+    } else if card == S_K {
+        return rules.king_points;
+    } else if card == S_A {
+        return rules.ace_points;
+    } else if card == D_J {
+        return rules.jack_points;
+    }
+    return 0;
+}
+
+fn is_penalty_card(card: i32, rules: &RuleSet) -> bool {
+    return get_penalty_point(card, rules) > 0;
+}
+
+fn breaks_heart(card: i32, rules: &RuleSet) -> bool {
+    return get_suit(card) == HEART || (rules.queen_breaks_hearts && card == S_Q);
+}
+
 //
 // The following functions are commonly used.
 //
@@ -250,58 +957,183 @@ fn get_suit(card: i32) -> i32 {
 }
 
 fn is_valid_card(
-    hand: &[i32; NUM_KC],
-    card_sequence: &[i32; NUM_PLAYERS],
+    hand: &[i32],
+    card_sequence: &[i32],
     card: i32,
     trick: usize,
     bh_flag: bool,
+    rules: &RuleSet,
 ) -> bool {
+    return get_illegal_reason(hand, card_sequence, card, trick, bh_flag, rules).is_none();
+}
+
+// Getting the reason why the card may not be played, or None if it may be played.
+fn get_illegal_reason(
+    hand: &[i32],
+    card_sequence: &[i32],
+    card: i32,
+    trick: usize,
+    bh_flag: bool,
+    rules: &RuleSet,
+) -> Option<IllegalReason> {
     // The first card played in a trick is called the "leading card" and
     // the agent who plays this card is called the "leading player".
     let leading_card = card_sequence[0];
 
     if leading_card == -1 {
-        // In the first trick, only Club-2 can be the leading card.
-        if trick == 0 && card != C_2 {
-            return false;
+        // In the first trick, only the opening card (Club-2 by default) can be the leading card;
+        // it may be led even if it is a heart (or S-Q that breaks hearts).
+        if trick == 0 {
+            if card != rules.get_opening_card() {
+                return Some(IllegalReason::MustLeadOpeningCard);
+            }
+            return None;
         }
 
-        // In the first trick, each agent cannot play a heart.
-        if trick == 0 && get_suit(card) == HEART {
-            return false;
+        // Hearts may be led at any time under some rules.
+        if rules.hearts_lead_anytime && get_suit(card) == HEART {
+            return None;
         }
 
-        // If the leading player has only hearts, it is an exceptional case and the agent may lead with a heart.
-        if get_suit(card) == HEART
-            && !is_suit_in_hand(hand, CLUB)
-            && !is_suit_in_hand(hand, DIA)
-            && !is_suit_in_hand(hand, SPADE)
-        {
-            return true;
+        // If S-Q breaks hearts, it is led under the same restriction as hearts.
+        let is_heart_like =
+            |c: i32| get_suit(c) == HEART || (rules.queen_breaks_hearts && c == S_Q);
+
+        // If the leading player has only hearts (and S-Q), it is an exceptional case and the agent may lead with a heart.
+        // S-Q may be led in that case only if the rules allow it or it is the last such card.
+        if !bh_flag && is_heart_like(card) && hand.iter().all(|h| *h == -1 || is_heart_like(*h)) {
+            if card != S_Q
+                || rules.queen_lead_with_hearts
+                || hand.iter().all(|h| *h == -1 || *h == S_Q)
+            {
+                return None;
+            }
+            return Some(IllegalReason::HeartsNotBroken);
         }
 
         // Until breaking heart occurs, the leading player may not play a heart.
-        if !bh_flag && get_suit(card) == HEART {
-            return false;
+        if !bh_flag && is_heart_like(card) {
+            return Some(IllegalReason::HeartsNotBroken);
         }
 
-        return true;
+        return None;
     } else {
-        // If an agent does not have a card of the same suit as the leading card, the agent play any card.
+        // If an agent does not have a card of the same suit as the leading card, the agent play any card;
+        // unless the rules prohibit penalty cards in the first trick and the agent has another card.
         if !is_suit_in_hand(hand, get_suit(leading_card)) {
-            return true;
+            if trick == 0
+                && !rules.points_on_first_trick
+                && is_penalty_card(card, rules)
+                && hand.iter().any(|h| *h != -1 && !is_penalty_card(*h, rules))
+            {
+                return Some(IllegalReason::NoPointsOnFirstTrick);
+            }
+            return None;
         }
 
         // Each agent must play a card of the same suit as the leading card.
         if get_suit(leading_card) == get_suit(card) {
-            return true;
+            return None;
         }
 
-        return false;
+        return Some(IllegalReason::MustFollowSuit);
+    }
+}
+
+fn get_valid_cards(
+    hand: &[i32],
+    card_sequence: &[i32],
+    trick: usize,
+    bh_flag: bool,
+    rules: &RuleSet,
+) -> Vec<i32> {
+    return hand
+        .iter()
+        .copied()
+        .filter(|c| *c != -1 && is_valid_card(hand, card_sequence, *c, trick, bh_flag, rules))
+        .collect();
+}
+
+// Choosing the card played instead of the agent's choice when the agent runs out of time;
+// it is -1 if no card is valid, which the engine reports as an illegal move.
+fn select_fallback_card(
+    hand: &[i32],
+    whole_card_sequence: &[i32; NUM_CARDS],
+    whole_agent_sequence: &[i32; NUM_CARDS],
+    trick: usize,
+    turn: usize,
+    bh_flag: bool,
+    seat: usize,
+    rules: &RuleSet,
+    rng: &mut ChaCha8Rng,
+) -> Result<i32, HeartsError> {
+    let num_players = rules.num_players;
+    let card_sequence = &whole_card_sequence[(trick * num_players)..((trick + 1) * num_players)];
+    let valid_cards = get_valid_cards(hand, card_sequence, trick, bh_flag, rules);
+
+    return match rules.timeout_fallback {
+        TimeoutFallback::Random => Ok(valid_cards.choose(rng).copied().unwrap_or(-1)),
+        TimeoutFallback::Lowest => Ok(valid_cards
+            .iter()
+            .copied()
+            .min_by_key(|c| (*c % (NUM_KC as i32), *c))
+            .unwrap_or(-1)),
+        TimeoutFallback::RuleBased => {
+            let mut agent = RuleBasedAgent::new();
+            agent.on_game_start(seat, rules);
+            agent.set_hand(hand)?;
+            agent.set_partner(rules.get_partner(seat));
+            Ok(agent.select_card(
+                whole_card_sequence,
+                whole_agent_sequence,
+                trick,
+                turn,
+                bh_flag,
+                rules,
+                TimeBudget::default(),
+            ))
+        }
+    };
+}
+
+// Checking that the agent at the seat has the card and may play it.
+fn validate_card(
+    hand: &[i32],
+    card_sequence: &[i32],
+    card: i32,
+    trick: usize,
+    bh_flag: bool,
+    seat: usize,
+    rules: &RuleSet,
+) -> Result<(), GameError> {
+    if card == -1 || !hand.contains(&card) {
+        return Err(GameError::CardNotInHand { seat, card });
+    }
+    if let Some(reason) = get_illegal_reason(hand, card_sequence, card, trick, bh_flag, rules) {
+        return Err(GameError::IllegalMove { seat, card, reason });
+    }
+    return Ok(());
+}
+
+// Checking the cards given as a hand (-1 for an empty slot), and returning them in order.
+fn check_hand(cards: &[i32]) -> Result<Vec<i32>, HeartsError> {
+    let mut hand = cards.to_vec();
+    hand.sort();
+    for i in 0..hand.len() {
+        if hand[i] == -1 {
+            continue;
+        }
+        if hand[i] < 0 || hand[i] >= NUM_CARDS as i32 {
+            return Err(HeartsError::InvalidCard(hand[i]));
+        }
+        if i > 0 && hand[i - 1] == hand[i] {
+            return Err(HeartsError::DuplicateCard(hand[i]));
+        }
     }
+    return Ok(hand);
 }
 
-fn is_suit_in_hand(hand: &[i32; NUM_KC], suit: i32) -> bool {
+fn is_suit_in_hand(hand: &[i32], suit: i32) -> bool {
     for h in hand {
         if *h != -1 && suit == get_suit(*h) {
             return true;
@@ -313,19 +1145,66 @@ fn is_suit_in_hand(hand: &[i32; NUM_KC], suit: i32) -> bool {
 //
 // Trait for agents
 //
+// The decision methods take the agent mutably, so that an agent can keep its own state across a game
+// (a memory of the played cards, a search tree reused between moves, its own random number generator, and so on).
+//
 
 trait Agent {
-    fn get_hand(&self) -> &[i32; NUM_KC];
-    fn set_hand(&mut self, cards: &[i32]);
+    fn get_hand(&self) -> &[i32];
+    fn set_hand(&mut self, cards: &[i32]) -> Result<(), HeartsError>;
     fn select_card(
-        &self,
+        &mut self,
         whole_card_sequence: &[i32; NUM_CARDS],
         whole_agent_sequence: &[i32; NUM_CARDS],
         trick: usize,
         turn: usize,
         bh_flag: bool,
+        rules: &RuleSet,
+        budget: TimeBudget,
     ) -> i32;
     fn update_hand(&mut self, card: i32);
+
+    // Telling the agent the seat of its partner at the beginning of a game (None unless in partnership mode).
+    fn set_partner(&mut self, _partner: Option<usize>) {}
+
+    // Giving the seed for the random choices of the agent in a game, so that games can be reproduced;
+    // agents without random choices ignore it.
+    fn set_seed(&mut self, _seed: u64) {}
+
+    // Notifications of the progress of the play, so that agents can update their internal state incrementally.
+    // They do nothing by default.
+
+    // Called before the cards are dealt, with the seat of the agent.
+    fn on_game_start(&mut self, _seat: usize, _rules: &RuleSet) {}
+
+    // Called when every player has played a card in the trick; the sequences are in the order of play.
+    fn on_trick_completed(
+        &mut self,
+        _trick: usize,
+        _card_sequence: &[i32],
+        _agent_sequence: &[i32],
+        _winner: usize,
+    ) {
+    }
+
+    // Called when all of the cards in the hands have been played, with the penalty points of the players
+    // in the game (including those for illegal moves and reneges).
+    fn on_game_end(&mut self, _penalty_points: &[i32]) {}
+
+    // Called when a match ends, with the total penalty points of the teams (or players) in the match.
+    fn on_match_end(&mut self, _match_points: &[i32]) {}
+
+    // Selecting the cards passed to another agent before a game; by default, the highest cards are passed.
+    fn select_passed_cards(&mut self, num_cards: usize) -> Vec<i32> {
+        let mut cards: Vec<i32> = self
+            .get_hand()
+            .iter()
+            .copied()
+            .filter(|c| *c != -1)
+            .collect();
+        cards.sort_by_key(|c| (c % (NUM_KC as i32), *c));
+        return cards.into_iter().rev().take(num_cards).collect();
+    }
 }
 
 //
@@ -336,49 +1215,63 @@ trait Agent {
 //
 
 struct RandomAgent {
-    hand: [i32; NUM_KC],
+    hand: Vec<i32>,
+    rng: ChaCha8Rng,
 }
 
 impl RandomAgent {
     fn new() -> Self {
-        Self { hand: [-1; NUM_KC] }
+        Self {
+            hand: Vec::new(),
+            rng: ChaCha8Rng::from_entropy(),
+        }
     }
 }
 
 impl Agent for RandomAgent {
-    fn get_hand(&self) -> &[i32; NUM_KC] {
+    fn get_hand(&self) -> &[i32] {
         &self.hand
     }
 
-    fn set_hand(&mut self, cards: &[i32]) {
-        self.hand = cards.try_into().unwrap();
-        self.hand.sort();
+    fn set_hand(&mut self, cards: &[i32]) -> Result<(), HeartsError> {
+        self.hand = check_hand(cards)?;
+        return Ok(());
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
     }
 
     // Randomly selecting a card from the hand.
     fn select_card(
-        &self,
+        &mut self,
         whole_card_sequence: &[i32; NUM_CARDS],
         _whole_agent_sequence: &[i32; NUM_CARDS],
         trick: usize,
         _turn: usize,
         bh_flag: bool,
+        rules: &RuleSet,
+        _budget: TimeBudget,
     ) -> i32 {
-        let mut card_sequence: [i32; NUM_PLAYERS] = [-1; NUM_PLAYERS];
-        for i in 0..NUM_PLAYERS {
-            card_sequence[i] = whole_card_sequence[trick * NUM_PLAYERS + i];
+        let num_players = rules.num_players;
+        let card_sequence =
+            &whole_card_sequence[(trick * num_players)..((trick + 1) * num_players)];
+
+        // No card is selected (-1) if none is valid, which the engine reports as an illegal move.
+        if get_valid_cards(&self.hand, card_sequence, trick, bh_flag, rules).is_empty() {
+            return -1;
         }
 
-        let mut rng = rand::thread_rng();
         loop {
-            let card_index = rng.gen_range(0..NUM_KC);
+            let card_index = self.rng.gen_range(0..self.hand.len());
             if self.hand[card_index] != -1
                 && is_valid_card(
                     &self.hand,
-                    &card_sequence,
+                    card_sequence,
                     self.hand[card_index],
                     trick,
                     bh_flag,
+                    rules,
                 )
             {
                 return self.hand[card_index];
@@ -386,8 +1279,17 @@ impl Agent for RandomAgent {
         }
     }
 
+    // Randomly selecting the cards to pass.
+    fn select_passed_cards(&mut self, num_cards: usize) -> Vec<i32> {
+        let cards: Vec<i32> = self.hand.iter().copied().filter(|c| *c != -1).collect();
+        return cards
+            .choose_multiple(&mut self.rng, num_cards)
+            .copied()
+            .collect();
+    }
+
     fn update_hand(&mut self, card: i32) {
-        for i in 0..NUM_KC {
+        for i in 0..self.hand.len() {
             if self.hand[i] == card {
                 self.hand[i] = -1;
                 break;
@@ -404,79 +1306,256 @@ impl Agent for RandomAgent {
 // in other words, the random agent acquired about 2.3-fold penalty points of rule-based agents on average.
 //
 
+// The constants used to score the cards in the rule-based agent.
+// They can be tuned as a parameter vector by the tune command (see tune.rs),
+// and loaded from a TOML or JSON file as a profile; missing entries take the hand-picked values.
+// profiles/default.toml is the profile of the hand-picked values.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RuleBasedParams {
+    // Leading player
+    lead_sq_score: i32,
+    lead_heart_bonus: i32,
+    lead_late_trick: i32,
+    lead_spade_with_sq_penalty: i32,
+    lead_suit_length_base: i32,
+    lead_broken_heart_base: i32,
+    // Following players
+    follow_sq_score: i32,
+    follow_high_spade_discard_base: i32,
+    follow_high_spade_with_sq_penalty: i32,
+    follow_high_spade_before_sq_penalty: i32,
+    follow_heart_on_heart_base: i32,
+    // D-J, when it has negative points (a bonus)
+    jack_capture_bonus: i32,
+    jack_feed_penalty: i32,
+    // Passing
+    pass_high_spade_base: i32,
+    pass_heart_base: i32,
+    // S-K and S-A, when they have penalty points (Black Maria)
+    lead_penalty_spade_score: i32,
+    follow_penalty_spade_keep_score: i32,
+    // Partnership mode
+    follow_partner_winning_score: i32,
+}
+
+const NUM_PARAMS: usize = 18;
+
+impl RuleBasedParams {
+    fn to_vec(self) -> [i32; NUM_PARAMS] {
+        return [
+            self.lead_sq_score,
+            self.lead_heart_bonus,
+            self.lead_late_trick,
+            self.lead_spade_with_sq_penalty,
+            self.lead_suit_length_base,
+            self.lead_broken_heart_base,
+            self.follow_sq_score,
+            self.follow_high_spade_discard_base,
+            self.follow_high_spade_with_sq_penalty,
+            self.follow_high_spade_before_sq_penalty,
+            self.follow_heart_on_heart_base,
+            self.jack_capture_bonus,
+            self.jack_feed_penalty,
+            self.pass_high_spade_base,
+            self.pass_heart_base,
+            self.lead_penalty_spade_score,
+            self.follow_penalty_spade_keep_score,
+            self.follow_partner_winning_score,
+        ];
+    }
+
+    fn load(path: &std::path::Path) -> std::io::Result<Self> {
+        return load_config(path);
+    }
+
+    fn from_vec(v: &[i32; NUM_PARAMS]) -> Self {
+        Self {
+            lead_sq_score: v[0],
+            lead_heart_bonus: v[1],
+            lead_late_trick: v[2],
+            lead_spade_with_sq_penalty: v[3],
+            lead_suit_length_base: v[4],
+            lead_broken_heart_base: v[5],
+            follow_sq_score: v[6],
+            follow_high_spade_discard_base: v[7],
+            follow_high_spade_with_sq_penalty: v[8],
+            follow_high_spade_before_sq_penalty: v[9],
+            follow_heart_on_heart_base: v[10],
+            jack_capture_bonus: v[11],
+            jack_feed_penalty: v[12],
+            pass_high_spade_base: v[13],
+            pass_heart_base: v[14],
+            lead_penalty_spade_score: v[15],
+            follow_penalty_spade_keep_score: v[16],
+            follow_partner_winning_score: v[17],
+        }
+    }
+}
+
+impl Default for RuleBasedParams {
+    // The hand-picked values.
+    fn default() -> Self {
+        Self {
+            lead_sq_score: -70,
+            lead_heart_bonus: 20,
+            lead_late_trick: 6,
+            lead_spade_with_sq_penalty: -50,
+            lead_suit_length_base: 10,
+            lead_broken_heart_base: 50,
+            follow_sq_score: -70,
+            follow_high_spade_discard_base: 50,
+            follow_high_spade_with_sq_penalty: -100,
+            follow_high_spade_before_sq_penalty: -50,
+            follow_heart_on_heart_base: 60,
+            jack_capture_bonus: 40,
+            jack_feed_penalty: -60,
+            pass_high_spade_base: 100,
+            pass_heart_base: 50,
+            lead_penalty_spade_score: -70,
+            follow_penalty_spade_keep_score: -70,
+            follow_partner_winning_score: -70,
+        }
+    }
+}
+
+// Explanation of a decision of the rule-based agent:
+// the score of every valid card and the rules that fired to compute it.
+struct Explanation {
+    seat: usize,
+    scores: Vec<CardScore>,
+    selected_card: i32,
+}
+
+struct CardScore {
+    card: i32,
+    score: i32,
+    rules: Vec<&'static str>,
+}
+
 struct RuleBasedAgent {
-    hand: [i32; NUM_KC],
+    hand: Vec<i32>,
+    params: RuleBasedParams,
+    // The seat given at the beginning of a game, which is shown in the explanations.
+    seat: usize,
+    partner: Option<usize>,
 }
 
 impl RuleBasedAgent {
     fn new() -> Self {
-        Self { hand: [-1; NUM_KC] }
+        Self::with_params(RuleBasedParams::default())
     }
 
-    fn calc_score(
+    fn with_params(params: RuleBasedParams) -> Self {
+        Self {
+            hand: Vec::new(),
+            params,
+            seat: 0,
+            partner: None,
+        }
+    }
+
+    // Scoring every valid card in the hand and selecting the card with the highest score.
+    fn explain(
         &self,
         whole_card_sequence: &[i32; NUM_CARDS],
         whole_agent_sequence: &[i32; NUM_CARDS],
         trick: usize,
         turn: usize,
         bh_flag: bool,
-    ) -> [i32; NUM_KC] {
-        let mut card_sequence: [i32; NUM_PLAYERS] = [-1; NUM_PLAYERS];
-        let mut agent_sequence: [i32; NUM_PLAYERS] = [-1; NUM_PLAYERS];
-        for i in 0..NUM_PLAYERS {
-            card_sequence[i] = whole_card_sequence[trick * NUM_PLAYERS + i];
-            agent_sequence[i] = whole_agent_sequence[trick * NUM_PLAYERS + i];
-        }
-
-        let mut score: [i32; NUM_KC] = [std::i32::MIN; NUM_KC];
-        for i in 0..NUM_KC {
+        rules: &RuleSet,
+    ) -> Explanation {
+        let num_players = rules.num_players;
+        let card_sequence =
+            &whole_card_sequence[(trick * num_players)..((trick + 1) * num_players)];
+        let agent_sequence =
+            &whole_agent_sequence[(trick * num_players)..((trick + 1) * num_players)];
+
+        let mut scores: Vec<CardScore> = Vec::new();
+        for i in 0..self.hand.len() {
             if self.hand[i] != -1
-                && is_valid_card(&self.hand, &card_sequence, self.hand[i], trick, bh_flag)
+                && is_valid_card(
+                    &self.hand,
+                    card_sequence,
+                    self.hand[i],
+                    trick,
+                    bh_flag,
+                    rules,
+                )
             {
-                if turn == 0 {
-                    score[i] = self.calc_score_first_turn(
+                let mut fired_rules: Vec<&'static str> = Vec::new();
+                let score = if turn == 0 {
+                    self.calc_score_first_turn(
                         whole_card_sequence,
                         whole_agent_sequence,
-                        &card_sequence,
-                        &agent_sequence,
+                        card_sequence,
+                        agent_sequence,
                         self.hand[i],
                         trick,
                         turn,
                         bh_flag,
-                    );
+                        rules,
+                        &mut fired_rules,
+                    )
                 } else {
-                    score[i] = self.calc_score_following_turn(
+                    self.calc_score_following_turn(
                         whole_card_sequence,
                         whole_agent_sequence,
-                        &card_sequence,
-                        &agent_sequence,
+                        card_sequence,
+                        agent_sequence,
                         self.hand[i],
                         trick,
                         turn,
                         bh_flag,
+                        rules,
+                        &mut fired_rules,
                     )
-                }
+                };
+                scores.push(CardScore {
+                    card: self.hand[i],
+                    score,
+                    rules: fired_rules,
+                });
             }
         }
-        return score;
+
+        // When some cards have the same score, the last one (the strongest card) is selected.
+        // No card is selected (-1) if none is valid, which the engine reports as an illegal move.
+        let mut selected_card = -1;
+        let mut max_score = i32::MIN;
+        for s in scores.iter() {
+            if s.score >= max_score {
+                selected_card = s.card;
+                max_score = s.score;
+            }
+        }
+
+        return Explanation {
+            seat: self.seat,
+            scores,
+            selected_card,
+        };
     }
 
     fn calc_score_first_turn(
         &self,
         whole_card_sequence: &[i32; NUM_CARDS],
         _whole_agent_sequence: &[i32; NUM_CARDS],
-        _card_sequence: &[i32; NUM_PLAYERS],
-        _agent_sequence: &[i32; NUM_PLAYERS],
+        _card_sequence: &[i32],
+        _agent_sequence: &[i32],
         card: i32,
         trick: usize,
         _turn: usize,
         bh_flag: bool,
+        rules: &RuleSet,
+        fired_rules: &mut Vec<&'static str>,
     ) -> i32 {
         let mut score = 0;
 
         // The score of discarding S-Q becomes low.
         if card == S_Q {
-            score = -70;
+            score = self.params.lead_sq_score;
+            fired_rules.push("S-Q is not led");
         }
 
         // The score of discarding S-K or S-A becomes low.
@@ -484,26 +1563,51 @@ impl RuleBasedAgent {
             && !self.is_card_discarded_in_game(whole_card_sequence, S_Q)
         {
             score = -card;
+            fired_rules.push("S-K/S-A is not led because S-Q is not yet discarded");
+        }
+
+        // S-K and S-A are not led either when they have their own penalty points (Black Maria).
+        if ((card == S_K) || (card == S_A)) && is_penalty_card(card, rules) {
+            score = self.params.lead_penalty_spade_score;
+            fired_rules.push("S-K/S-A is not led because it has penalty points");
         }
 
         if get_suit(card) == HEART {
-            score = -card + 20;
+            score = -card + self.params.lead_heart_bonus;
+            fired_rules.push("heart is led; the bigger number, the lower score");
         }
 
-        if trick >= 6 {
-            score = score - self.get_symbol(card);
+        if trick as i32 >= self.params.lead_late_trick {
+            score -= self.get_symbol(card);
+            fired_rules.push("late trick; the bigger number, the lower score");
         }
 
         if (get_suit(card) == SPADE) && self.is_card_in_hand(S_Q) {
-            score = -50 + score;
+            score += self.params.lead_spade_with_sq_penalty;
+            fired_rules.push("spade is not led because S-Q is in hand");
         }
 
         if (card != S_Q) && (card != S_K) && (card != S_A) {
-            score = 10 - self.count_number_of_suit_in_hand(card) - get_suit(card) + score;
+            score += self.params.lead_suit_length_base
+                - self.count_number_of_suit_in_hand(card)
+                - get_suit(card);
+            fired_rules.push("the fewer cards of the suit in hand, the higher score");
         }
 
         if bh_flag && (get_suit(card) == HEART) {
-            score += 50 - card;
+            score += self.params.lead_broken_heart_base - card;
+            fired_rules.push("heart is led after breaking heart");
+        }
+
+        // D-J is led only when no other player has a higher diamond, so that the agent wins it.
+        if (card == D_J) && (rules.jack_points < 0) {
+            if self.is_jack_highest(whole_card_sequence) {
+                score += self.params.jack_capture_bonus;
+                fired_rules.push("D-J is led because no higher diamond is left");
+            } else {
+                score += self.params.jack_feed_penalty;
+                fired_rules.push("D-J is not led because a higher diamond is left");
+            }
         }
 
         return score;
@@ -513,24 +1617,37 @@ impl RuleBasedAgent {
         &self,
         whole_card_sequence: &[i32; NUM_CARDS],
         _whole_agent_sequence: &[i32; NUM_CARDS],
-        card_sequence: &[i32; NUM_PLAYERS],
-        _agent_sequence: &[i32; NUM_PLAYERS],
+        card_sequence: &[i32],
+        agent_sequence: &[i32],
         card: i32,
         _trick: usize,
         turn: usize,
         _bh_flag: bool,
+        rules: &RuleSet,
+        fired_rules: &mut Vec<&'static str>,
     ) -> i32 {
         let mut score = 0;
         let leading_card = card_sequence[0];
 
+        // In partnership mode, no penalty card is dumped on a trick that the partner is winning.
+        if is_penalty_card(card, rules)
+            && (get_suit(card) != get_suit(leading_card))
+            && self.is_partner_winning(card_sequence, agent_sequence, turn)
+        {
+            fired_rules.push("penalty card kept because the partner is winning the trick");
+            return self.params.follow_partner_winning_score - get_penalty_point(card, rules);
+        }
+
         // The score of discarding S-Q becomes low.
         if card == S_Q {
-            score = -70;
+            score = self.params.follow_sq_score;
+            fired_rules.push("S-Q is not discarded");
         }
 
         // The score of discarding S-K or S-A becomes low.
         if (card == S_K) || (card == S_A) {
             score = -card;
+            fired_rules.push("S-K/S-A is not discarded");
         }
 
         // If S-K or S-A is discarded in the trick, the score to discard S-Q becomes MAX.
@@ -538,12 +1655,30 @@ impl RuleBasedAgent {
             && (self.is_card_discarded_in_trick(card_sequence, S_K)
                 || self.is_card_discarded_in_trick(card_sequence, S_A))
         {
-            return std::i32::MAX;
+            fired_rules.push("S-Q dumped because S-K/S-A is discarded in the trick");
+            return i32::MAX;
         }
 
         // If the suit of the leading card is not SPADE, the agent must immediately discard S-Q.
         if (card == S_Q) && (get_suit(card) != get_suit(leading_card)) {
-            return std::i32::MAX;
+            fired_rules.push("S-Q dumped because leading suit is not spade");
+            return i32::MAX;
+        }
+
+        // When S-K and S-A have their own penalty points (Black Maria), they are dumped whenever
+        // they do not win the trick, and kept otherwise.
+        if ((card == S_K) || (card == S_A)) && is_penalty_card(card, rules) {
+            if get_suit(card) != get_suit(leading_card) {
+                fired_rules
+                    .push("S-K/S-A with penalty points dumped because leading suit is not spade");
+                return i32::MAX.saturating_sub(self.params.follow_high_spade_discard_base - card);
+            }
+            if !self.is_winning_card(card_sequence, card) {
+                fired_rules.push("S-K/S-A with penalty points dumped under a higher spade");
+                return i32::MAX.saturating_sub(self.params.follow_high_spade_discard_base - card);
+            }
+            fired_rules.push("S-K/S-A with penalty points kept because it would win the trick");
+            return self.params.follow_penalty_spade_keep_score + card;
         }
 
         // If the suit of the leading card is not SPADE, the priority to discard S-K or S-A becomes high.
@@ -551,7 +1686,8 @@ impl RuleBasedAgent {
             && (get_suit(card) != get_suit(leading_card))
             && !self.is_card_discarded_in_game(whole_card_sequence, S_Q)
         {
-            return std::i32::MAX - (50 - card);
+            fired_rules.push("S-K/S-A dumped because leading suit is not spade");
+            return i32::MAX.saturating_sub(self.params.follow_high_spade_discard_base - card);
         }
 
         // If S-Q is discarded in the trick, the score to discard S-K or S-A becomes low.
@@ -559,39 +1695,76 @@ impl RuleBasedAgent {
             && self.is_card_discarded_in_trick(card_sequence, S_Q)
             && (get_suit(card) == get_suit(leading_card))
         {
-            return -100 + card + score;
+            fired_rules.push("S-K/S-A kept because S-Q is discarded in the trick");
+            return self.params.follow_high_spade_with_sq_penalty + card + score;
         }
 
         // If S-Q is not yet discarded in the game, the score to discard S-K or S-A becomes low.
         if ((card == S_K) || (card == S_A))
             && !self.is_card_discarded_in_game(whole_card_sequence, S_Q)
         {
-            return -50 + card + score;
+            fired_rules.push("S-K/S-A kept because S-Q is not yet discarded");
+            return self.params.follow_high_spade_before_sq_penalty + card + score;
         }
 
         // If S-Q has not been discarded on the last turn of the trick, the score to discard S-K or S-A becomes high.
-        if (turn == NUM_PLAYERS - 1)
+        if (turn == rules.num_players - 1)
             && ((card == S_K) || (card == S_A))
             && !self.is_card_discarded_in_trick(card_sequence, S_Q)
         {
+            fired_rules.push("S-K/S-A played safely on the last turn");
             return card;
         }
 
         // If the suit of the leading card is not HEART, the priority to discard HEART becomes high.
         if (get_suit(card) == HEART) && (get_suit(card) != get_suit(leading_card)) {
             score = card;
+            fired_rules.push("heart dumped because leading suit is not heart");
         }
 
         // If the suit of the leading card is HEART, the bigger number, the lower score.
         if (get_suit(card) == HEART) && (get_suit(leading_card) == HEART) {
-            score = 60 - card;
+            score = self.params.follow_heart_on_heart_base - card;
+            fired_rules.push("leading suit is heart; the bigger number, the lower score");
+        }
+
+        if rules.jack_points < 0 {
+            // D-J is played only when it surely wins the trick; otherwise, it would be fed to another player.
+            if card == D_J {
+                if self.is_winning_card(card_sequence, card)
+                    && ((turn == rules.num_players - 1)
+                        || self.is_jack_highest(whole_card_sequence))
+                {
+                    score += self.params.jack_capture_bonus;
+                    fired_rules.push("D-J played because it wins the trick");
+                } else {
+                    score += self.params.jack_feed_penalty;
+                    fired_rules.push("D-J kept because it would be fed to another player");
+                }
+            }
+
+            // If D-J is discarded in the trick, the agent tries to win the trick unless it has more penalty points.
+            if (card != D_J)
+                && self.is_card_discarded_in_trick(card_sequence, D_J)
+                && self.is_winning_card(card_sequence, card)
+                && card_sequence
+                    .iter()
+                    .chain([card].iter())
+                    .filter(|c| **c != -1)
+                    .map(|c| get_penalty_point(*c, rules))
+                    .sum::<i32>()
+                    < 0
+            {
+                score += self.params.jack_capture_bonus;
+                fired_rules.push("D-J is won in the trick");
+            }
         }
 
         return score;
     }
 
-    fn is_card_discarded_in_trick(&self, card_sequence: &[i32; NUM_PLAYERS], card: i32) -> bool {
-        for i in 0..NUM_PLAYERS {
+    fn is_card_discarded_in_trick(&self, card_sequence: &[i32], card: i32) -> bool {
+        for i in 0..card_sequence.len() {
             if card_sequence[i] == card {
                 return true;
             }
@@ -605,16 +1778,59 @@ impl RuleBasedAgent {
                 return true;
             } else if whole_card_sequence[i] == -1 {
                 break;
-            } else {
             }
         }
         return false;
     }
 
+    // Whether the card is the strongest one in the trick so far.
+    fn is_winning_card(&self, card_sequence: &[i32], card: i32) -> bool {
+        let leading_card = card_sequence[0];
+        if leading_card == -1 {
+            return true;
+        }
+        if get_suit(card) != get_suit(leading_card) {
+            return false;
+        }
+        for c in card_sequence {
+            if (get_suit(*c) == get_suit(card)) && (*c > card) {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    // Whether the partner has played the strongest card in the trick so far.
+    fn is_partner_winning(
+        &self,
+        card_sequence: &[i32],
+        agent_sequence: &[i32],
+        turn: usize,
+    ) -> bool {
+        return match self.partner {
+            Some(partner) => {
+                determine_winner(&agent_sequence[..turn], &card_sequence[..turn]) == partner as i32
+            }
+            None => false,
+        };
+    }
+
+    // Whether D-Q, D-K and D-A are either discarded in the game or in the hand.
+    fn is_jack_highest(&self, whole_card_sequence: &[i32; NUM_CARDS]) -> bool {
+        for card in (D_J + 1)..((DIA + 1) * (NUM_KC as i32)) {
+            if !self.is_card_discarded_in_game(whole_card_sequence, card)
+                && !self.is_card_in_hand(card)
+            {
+                return false;
+            }
+        }
+        return true;
+    }
+
     fn count_number_of_suit_in_hand(&self, card: i32) -> i32 {
         let suit = get_suit(card);
         let mut count = 0;
-        for i in 0..NUM_KC {
+        for i in 0..self.hand.len() {
             if suit == get_suit(self.hand[i]) {
                 count += 1
             }
@@ -623,7 +1839,7 @@ impl RuleBasedAgent {
     }
 
     fn is_card_in_hand(&self, card: i32) -> bool {
-        for i in 0..NUM_KC {
+        for i in 0..self.hand.len() {
             if self.hand[i] == card {
                 return true;
             }
@@ -637,52 +1853,80 @@ impl RuleBasedAgent {
 }
 
 impl Agent for RuleBasedAgent {
-    fn get_hand(&self) -> &[i32; NUM_KC] {
+    fn get_hand(&self) -> &[i32] {
         &self.hand
     }
 
-    fn set_hand(&mut self, cards: &[i32]) {
-        self.hand = cards.try_into().unwrap();
-        self.hand.sort();
+    fn set_hand(&mut self, cards: &[i32]) -> Result<(), HeartsError> {
+        self.hand = check_hand(cards)?;
+        return Ok(());
     }
 
     fn select_card(
-        &self,
+        &mut self,
         whole_card_sequence: &[i32; NUM_CARDS],
         whole_agent_sequence: &[i32; NUM_CARDS],
         trick: usize,
         turn: usize,
         bh_flag: bool,
+        rules: &RuleSet,
+        _budget: TimeBudget,
     ) -> i32 {
-        let score = self.calc_score(
+        let explanation = self.explain(
             whole_card_sequence,
             whole_agent_sequence,
             trick,
             turn,
             bh_flag,
+            rules,
         );
-        // if DEBUG_OUTPUT {
-        //     print_score(&score);
-        // }
+        trace!(target: logging::AGENT, "{}", format_explanation(&explanation));
+
+        return explanation.selected_card;
+    }
 
-        let mut idx = 0;
-        for (j, &value) in score.iter().enumerate() {
-            if value >= score[idx] {
-                idx = j
+    // Passing S-Q, S-K and S-A first, then high hearts, and then high cards of short suits.
+    fn select_passed_cards(&mut self, num_cards: usize) -> Vec<i32> {
+        let mut scored_cards: Vec<(i32, i32)> = Vec::new();
+        for i in 0..self.hand.len() {
+            let card = self.hand[i];
+            if card == -1 {
+                continue;
             }
+            let score = if (card == S_Q) || (card == S_K) || (card == S_A) {
+                self.params.pass_high_spade_base + self.get_symbol(card)
+            } else if get_suit(card) == HEART {
+                self.params.pass_heart_base + self.get_symbol(card)
+            } else {
+                self.get_symbol(card) - self.count_number_of_suit_in_hand(card)
+            };
+            scored_cards.push((score, card));
         }
-
-        return self.hand[idx];
+        scored_cards.sort();
+        return scored_cards
+            .iter()
+            .rev()
+            .take(num_cards)
+            .map(|c| c.1)
+            .collect();
     }
 
     fn update_hand(&mut self, card: i32) {
-        for i in 0..NUM_KC {
+        for i in 0..self.hand.len() {
             if self.hand[i] == card {
                 self.hand[i] = -1;
                 break;
             }
         }
     }
+
+    fn set_partner(&mut self, partner: Option<usize>) {
+        self.partner = partner;
+    }
+
+    fn on_game_start(&mut self, seat: usize, _rules: &RuleSet) {
+        self.seat = seat;
+    }
 }
 
 // Below for debug.
@@ -694,27 +1938,556 @@ const CARD_NAME: [&str; NUM_CARDS] = [
     "H-2", "H-3", "H-4", "H-5", "H-6", "H-7", "H-8", "H-9", "H-10", "H-J", "H-Q", "H-K", "H-A",
 ];
 
-fn print_hand(hand: &[i32; NUM_KC], agent_no: usize) {
-    print!("{}: ", agent_no + 1);
-    for i in 0..NUM_KC {
-        if hand[i] == -1 {
-            continue;
+// The cards are separated by commas, skipping -1.
+fn format_cards(cards: &[i32]) -> String {
+    let names: Vec<&str> = cards
+        .iter()
+        .filter(|c| **c != -1)
+        .map(|c| CARD_NAME[*c as usize])
+        .collect();
+    return names.join(", ");
+}
+
+fn format_hand(hand: &[i32], agent_no: usize) -> String {
+    return format!("{}: {}", agent_no + 1, format_cards(hand));
+}
+
+fn format_explanation(explanation: &Explanation) -> String {
+    let mut text = format!(
+        "Agent {} selected {}",
+        explanation.seat + 1,
+        CARD_NAME
+            .get(explanation.selected_card as usize)
+            .unwrap_or(&"--")
+    );
+    for s in explanation.scores.iter() {
+        if s.rules.is_empty() {
+            text += &format!(
+                "\n  {}: {} [no rule fired]",
+                CARD_NAME[s.card as usize], s.score
+            );
+        } else {
+            text += &format!(
+                "\n  {}: {} [{}]",
+                CARD_NAME[s.card as usize],
+                s.score,
+                s.rules.join("; ")
+            );
         }
-        print!("{}, ", CARD_NAME[hand[i] as usize]);
     }
-    println!("");
+    return text;
 }
 
-fn print_card(card: i32) {
-    println!("{}", CARD_NAME[card as usize]);
-}
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::legality::Renege;
+
+    fn card(name: &str) -> i32 {
+        return CARD_NAME.iter().position(|n| *n == name).unwrap() as i32;
+    }
+
+    fn cards(names: &[&str]) -> Vec<i32> {
+        return names.iter().map(|n| card(n)).collect();
+    }
+
+    fn rules_with(
+        queen_breaks_hearts: bool,
+        hearts_lead_anytime: bool,
+        queen_lead_with_hearts: bool,
+    ) -> RuleSet {
+        return RuleSet {
+            queen_breaks_hearts,
+            hearts_lead_anytime,
+            queen_lead_with_hearts,
+            ..RuleSet::default()
+        };
+    }
+
+    // (queen_breaks_hearts, hearts_lead_anytime, queen_lead_with_hearts), hand, legal leads
+    type LeadCase = (
+        (bool, bool, bool),
+        &'static [&'static str],
+        &'static [&'static str],
+    );
+
+    // The cards that may be led from the hand in the second trick.
+    fn leads(hand: &[&str], bh_flag: bool, rules: &RuleSet) -> Vec<i32> {
+        return get_valid_cards(&cards(hand), &[-1; 4], 1, bh_flag, rules);
+    }
+
+    #[test]
+    fn leading_before_hearts_are_broken() {
+        let cases: [LeadCase; 14] = [
+            (
+                (false, false, false),
+                &["C-5", "S-Q", "H-3"],
+                &["C-5", "S-Q"],
+            ),
+            ((true, false, false), &["C-5", "S-Q", "H-3"], &["C-5"]),
+            (
+                (false, true, false),
+                &["C-5", "S-Q", "H-3"],
+                &["C-5", "S-Q", "H-3"],
+            ),
+            ((true, true, false), &["C-5", "S-Q", "H-3"], &["C-5", "H-3"]),
+            ((true, false, true), &["C-5", "S-Q", "H-3"], &["C-5"]),
+            ((false, false, false), &["H-3", "H-9"], &["H-3", "H-9"]),
+            ((true, false, false), &["H-3", "H-9"], &["H-3", "H-9"]),
+            // Only hearts and S-Q in the hand.
+            ((false, false, false), &["S-Q", "H-3"], &["S-Q"]),
+            ((true, false, false), &["S-Q", "H-3"], &["H-3"]),
+            ((true, false, true), &["S-Q", "H-3"], &["S-Q", "H-3"]),
+            ((false, true, false), &["S-Q", "H-3"], &["S-Q", "H-3"]),
+            ((true, true, false), &["S-Q", "H-3"], &["H-3"]),
+            ((true, true, true), &["S-Q", "H-3"], &["S-Q", "H-3"]),
+            // S-Q may be led if it is the last card.
+            ((true, false, false), &["S-Q"], &["S-Q"]),
+        ];
+        for ((qbh, hla, qlwh), hand, expected) in cases.iter() {
+            let rules = rules_with(*qbh, *hla, *qlwh);
+            assert_eq!(
+                leads(hand, false, &rules),
+                cards(expected),
+                "queen_breaks_hearts = {}, hearts_lead_anytime = {}, queen_lead_with_hearts = {}, hand {:?}",
+                qbh,
+                hla,
+                qlwh,
+                hand
+            );
+        }
+    }
+
+    #[test]
+    fn leading_after_hearts_are_broken() {
+        for qbh in [false, true] {
+            for hla in [false, true] {
+                for qlwh in [false, true] {
+                    let rules = rules_with(qbh, hla, qlwh);
+                    assert_eq!(
+                        leads(&["C-5", "S-Q", "H-3"], true, &rules),
+                        cards(&["C-5", "S-Q", "H-3"])
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn first_trick_is_led_with_opening_card() {
+        let rules = rules_with(true, true, true);
+        let valid = get_valid_cards(&cards(&["C-2", "H-3"]), &[-1; 4], 0, false, &rules);
+        assert_eq!(valid, cards(&["C-2"]));
+    }
+
+    // The tricks of a game of four players, in each of which the first card wins:
+    // the other cards are of other suits or lower. D-J is in a trick without hearts.
+    const TRICKS: [[&str; 4]; 13] = [
+        ["H-J", "D-2", "S-2", "H-2"],
+        ["C-3", "D-3", "S-3", "H-3"],
+        ["C-4", "D-4", "S-4", "H-4"],
+        ["C-5", "D-5", "S-5", "H-5"],
+        ["C-6", "D-6", "S-6", "H-6"],
+        ["C-7", "D-7", "S-7", "H-7"],
+        ["C-8", "D-8", "S-8", "H-8"],
+        ["C-9", "D-9", "S-9", "H-9"],
+        ["C-10", "D-10", "S-10", "H-10"],
+        ["C-J", "D-J", "S-J", "C-2"],
+        ["C-Q", "D-Q", "S-Q", "H-Q"],
+        ["C-K", "D-K", "S-K", "H-K"],
+        ["C-A", "D-A", "S-A", "H-A"],
+    ];
+    const TRICK_WITH_JACK: usize = 9;
+    const TRICK_WITH_KING: usize = 11;
+    const TRICK_WITH_ACE: usize = 12;
+
+    // The sequences of a game in which each trick is led and won by the given player.
+    fn sequences(winners: [usize; 13]) -> ([i32; NUM_CARDS], [i32; NUM_CARDS]) {
+        let mut card_sequence = [-1; NUM_CARDS];
+        let mut agent_sequence = [-1; NUM_CARDS];
+        for (trick, winner) in winners.iter().enumerate() {
+            for turn in 0..4 {
+                card_sequence[trick * 4 + turn] = card(TRICKS[trick][turn]);
+                agent_sequence[trick * 4 + turn] = ((winner + turn) % 4) as i32;
+            }
+        }
+        return (card_sequence, agent_sequence);
+    }
+
+    // All of the tricks are won by seat 0 except those given.
+    fn winners_except(others: &[(usize, usize)]) -> [usize; 13] {
+        let mut winners = [0; 13];
+        for (trick, seat) in others.iter() {
+            winners[*trick] = *seat;
+        }
+        return winners;
+    }
+
+    fn score(winners: [usize; 13], rules: &RuleSet) -> Vec<i32> {
+        let (card_sequence, agent_sequence) = sequences(winners);
+        return calc_penalty_points(&card_sequence, &agent_sequence, rules);
+    }
+
+    #[test]
+    fn moon_shot_scoring() {
+        let omnibus = |moon_shot: MoonShot, moon_requires_jack: bool| RuleSet {
+            jack_points: -10,
+            moon_shot,
+            moon_requires_jack,
+            ..RuleSet::default()
+        };
+        let all = winners_except(&[]);
+        let jack_to_1 = winners_except(&[(TRICK_WITH_JACK, 1)]);
+        let heart_to_2 = winners_except(&[(1, 2)]);
+
+        let cases: [(RuleSet, [usize; 13], Vec<i32>); 10] = [
+            (omnibus(MoonShot::Disabled, false), all, vec![16, 0, 0, 0]),
+            (
+                omnibus(MoonShot::AddToOthers, false),
+                all,
+                vec![-10, 26, 26, 26],
+            ),
+            (
+                omnibus(MoonShot::SubtractFromSelf, false),
+                all,
+                vec![-36, 0, 0, 0],
+            ),
+            // D-J does not count for the moon, and its points go to whoever took it.
+            (
+                omnibus(MoonShot::AddToOthers, false),
+                jack_to_1,
+                vec![0, 16, 26, 26],
+            ),
+            (
+                omnibus(MoonShot::SubtractFromSelf, false),
+                jack_to_1,
+                vec![-26, -10, 0, 0],
+            ),
+            // The shooter must also take D-J.
+            (
+                omnibus(MoonShot::AddToOthers, true),
+                jack_to_1,
+                vec![26, -10, 0, 0],
+            ),
+            (
+                omnibus(MoonShot::AddToOthers, true),
+                all,
+                vec![-10, 26, 26, 26],
+            ),
+            // Nobody shoots the moon if a heart is missing.
+            (
+                omnibus(MoonShot::AddToOthers, false),
+                heart_to_2,
+                vec![15, 0, 1, 0],
+            ),
+            (
+                omnibus(MoonShot::SubtractFromSelf, false),
+                heart_to_2,
+                vec![15, 0, 1, 0],
+            ),
+            (
+                RuleSet {
+                    moon_shot: MoonShot::AddToOthers,
+                    ..RuleSet::default()
+                },
+                all,
+                vec![0, 26, 26, 26],
+            ),
+        ];
+        for (i, (rules, winners, expected)) in cases.iter().enumerate() {
+            assert_eq!(&score(*winners, rules), expected, "case {}", i);
+        }
+    }
+
+    #[test]
+    fn black_maria_scoring() {
+        let rules = RuleSet {
+            king_points: 10,
+            ace_points: 7,
+            moon_shot: MoonShot::AddToOthers,
+            ..RuleSet::default()
+        };
+        assert_eq!(rules.get_total_penalty_points(), 43);
+        let black_maria = RuleSet {
+            num_players: 3,
+            ..rules.clone()
+        };
+        assert_eq!(black_maria.get_total_penalty_points(), 43);
+
+        let winners = winners_except(&[(TRICK_WITH_KING, 1), (TRICK_WITH_ACE, 2)]);
+        assert_eq!(score(winners, &rules), vec![24, 11, 8, 0]);
+        assert_eq!(score(winners_except(&[]), &rules), vec![0, 43, 43, 43]);
+    }
+
+    #[test]
+    fn spot_hearts_scoring() {
+        let rules = RuleSet {
+            heart_scoring: HeartScoring::Spot,
+            moon_shot: MoonShot::AddToOthers,
+            ..RuleSet::default()
+        };
+        assert_eq!(rules.get_total_penalty_points(), 117);
+        // H-A (14) and H-K (13) go to other players.
+        let winners = winners_except(&[(TRICK_WITH_KING, 1), (TRICK_WITH_ACE, 2)]);
+        assert_eq!(score(winners, &rules), vec![90, 13, 14, 0]);
+        assert_eq!(score(winners_except(&[]), &rules), vec![0, 117, 117, 117]);
+
+        let rules = RuleSet {
+            heart_scoring: HeartScoring::SpotFaceTen,
+            ..rules
+        };
+        assert_eq!(rules.get_total_penalty_points(), 111);
+    }
+
+    // An agent that plays its first valid card, except that it fails to follow suit the first time it can,
+    // and records the renege in the shared log.
+    struct RenegingAgent {
+        hand: Vec<i32>,
+        seat: usize,
+        reneges: Rc<RefCell<Vec<Renege>>>,
+    }
+
+    impl Agent for RenegingAgent {
+        fn get_hand(&self) -> &[i32] {
+            &self.hand
+        }
+
+        fn set_hand(&mut self, cards: &[i32]) -> Result<(), HeartsError> {
+            self.hand = check_hand(cards)?;
+            return Ok(());
+        }
+
+        fn on_game_start(&mut self, seat: usize, _rules: &RuleSet) {
+            self.seat = seat;
+        }
+
+        fn select_card(
+            &mut self,
+            whole_card_sequence: &[i32; NUM_CARDS],
+            _whole_agent_sequence: &[i32; NUM_CARDS],
+            trick: usize,
+            _turn: usize,
+            bh_flag: bool,
+            rules: &RuleSet,
+            _budget: TimeBudget,
+        ) -> i32 {
+            let card_sequence = &whole_card_sequence[(trick * 4)..((trick + 1) * 4)];
+            let valid_cards = get_valid_cards(&self.hand, card_sequence, trick, bh_flag, rules);
+            let off_suit = self.hand.iter().copied().find(|c| {
+                *c != -1
+                    && card_sequence[0] != -1
+                    && get_suit(*c) != get_suit(card_sequence[0])
+                    && !valid_cards.contains(c)
+            });
+            let has_reneged = !self.reneges.borrow().is_empty();
+            if let Some(card) = off_suit.filter(|_| !has_reneged) {
+                self.reneges.borrow_mut().push(Renege {
+                    seat: self.seat,
+                    trick,
+                    card,
+                });
+                return card;
+            }
+            return valid_cards[0];
+        }
+
+        fn update_hand(&mut self, card: i32) {
+            for c in self.hand.iter_mut() {
+                if *c == card {
+                    *c = -1;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn renege_is_penalized_after_the_game() {
+        let rules = RuleSet {
+            allow_renege: true,
+            renege_points: 20,
+            ..RuleSet::default()
+        };
+        let reneges = Rc::new(RefCell::new(Vec::new()));
+        let mut agents: Vec<Box<dyn Agent>> = vec![Box::new(RenegingAgent {
+            hand: Vec::new(),
+            seat: 0,
+            reneges: reneges.clone(),
+        })];
+        for _ in 1..4 {
+            agents.push(Box::new(RuleBasedAgent::new()));
+        }
+
+        let mut whole_card_sequence = [-1; NUM_CARDS];
+        let mut whole_agent_sequence = [-1; NUM_CARDS];
+        let mut extra_penalty_points = vec![0; 4];
+        let dealt_cards = play_one_game(
+            &mut agents,
+            &rules,
+            1,
+            42,
+            &mut whole_card_sequence,
+            &mut whole_agent_sequence,
+            &mut extra_penalty_points,
+        )
+        .unwrap();
+
+        // The renege is accepted during the play, found afterwards and penalized once;
+        // the other players, who discard only when void, are not penalized.
+        let reneges = reneges.borrow();
+        assert_eq!(reneges.len(), 1);
+        let idx = whole_card_sequence
+            .iter()
+            .position(|c| *c == reneges[0].card)
+            .unwrap();
+        assert_eq!((idx / 4, whole_agent_sequence[idx]), (reneges[0].trick, 0));
+        assert_eq!(
+            detect_reneges(
+                &dealt_cards,
+                &whole_card_sequence,
+                &whole_agent_sequence,
+                &rules
+            ),
+            *reneges
+        );
+        assert_eq!(extra_penalty_points, vec![20, 0, 0, 0]);
+    }
+
+    // An agent that always plays the lowest card that it does not hold, and records its choices in the shared log.
+    struct CheatingAgent {
+        hand: Vec<i32>,
+        choices: Rc<RefCell<Vec<i32>>>,
+    }
+
+    impl Agent for CheatingAgent {
+        fn get_hand(&self) -> &[i32] {
+            &self.hand
+        }
+
+        fn set_hand(&mut self, cards: &[i32]) -> Result<(), HeartsError> {
+            self.hand = check_hand(cards)?;
+            return Ok(());
+        }
+
+        fn select_card(
+            &mut self,
+            _whole_card_sequence: &[i32; NUM_CARDS],
+            _whole_agent_sequence: &[i32; NUM_CARDS],
+            _trick: usize,
+            _turn: usize,
+            _bh_flag: bool,
+            _rules: &RuleSet,
+            _budget: TimeBudget,
+        ) -> i32 {
+            let card = (0..NUM_CARDS as i32)
+                .find(|c| !self.hand.contains(c))
+                .unwrap();
+            self.choices.borrow_mut().push(card);
+            return card;
+        }
+
+        fn update_hand(&mut self, card: i32) {
+            for c in self.hand.iter_mut() {
+                if *c == card {
+                    *c = -1;
+                }
+            }
+        }
+    }
+
+    struct CheatedGame {
+        result: Result<Vec<i32>, HeartsError>,
+        whole_card_sequence: [i32; NUM_CARDS],
+        whole_agent_sequence: [i32; NUM_CARDS],
+        extra_penalty_points: Vec<i32>,
+        choices: Vec<i32>,
+    }
+
+    // Playing a game in which the agent at seat 0 cheats on every move, under the given policy.
+    fn play_with_cheater(illegal_move: IllegalMovePolicy) -> CheatedGame {
+        let rules = RuleSet {
+            illegal_move,
+            illegal_move_points: 5,
+            ..RuleSet::default()
+        };
+        let choices = Rc::new(RefCell::new(Vec::new()));
+        let mut agents: Vec<Box<dyn Agent>> = vec![Box::new(CheatingAgent {
+            hand: Vec::new(),
+            choices: choices.clone(),
+        })];
+        for _ in 1..4 {
+            agents.push(Box::new(RuleBasedAgent::new()));
+        }
+
+        let mut whole_card_sequence = [-1; NUM_CARDS];
+        let mut whole_agent_sequence = [-1; NUM_CARDS];
+        let mut extra_penalty_points = vec![0; 4];
+        let result = play_one_game(
+            &mut agents,
+            &rules,
+            1,
+            7,
+            &mut whole_card_sequence,
+            &mut whole_agent_sequence,
+            &mut extra_penalty_points,
+        );
+        let choices = choices.borrow().clone();
+        return CheatedGame {
+            result,
+            whole_card_sequence,
+            whole_agent_sequence,
+            extra_penalty_points,
+            choices,
+        };
+    }
+
+    // Checking that every card of the game was legal by replaying it from the dealt cards.
+    fn assert_legal_game(game: &CheatedGame) {
+        let dealt_cards = game.result.as_ref().unwrap();
+        let rules = RuleSet::default();
+        let hands: Vec<Vec<i32>> = dealt_cards.chunks(13).map(|h| h.to_vec()).collect();
+        let mut state = legality::GameState::new(hands, &rules);
+        for idx in 0..NUM_CARDS {
+            assert_eq!(state.seat as i32, game.whole_agent_sequence[idx]);
+            state.play(game.whole_card_sequence[idx], &rules).unwrap();
+        }
+        assert!(state.is_finished());
+    }
 
-// fn print_score(score: &[i32; NUM_KC]) {
-//     print!("[");
-//     for i in 0..NUM_KC {
-//         if score[i] != std::i32::MIN {
-//             print!("{}, ", score[i]);
-//         }
-//     }
-//     println!("]");
-// }
+    #[test]
+    fn illegal_move_aborts_the_game() {
+        let game = play_with_cheater(IllegalMovePolicy::Abort);
+        assert_eq!(game.choices.len(), 1);
+        assert!(matches!(
+            game.result,
+            Err(HeartsError::Game(GameError::CardNotInHand { seat: 0, card })) if card == game.choices[0]
+        ));
+    }
+
+    #[test]
+    fn illegal_move_is_replaced_with_a_legal_card() {
+        for (policy, points) in [
+            (IllegalMovePolicy::Random, 0),
+            (IllegalMovePolicy::Penalize, 5 * 13),
+        ] {
+            let game = play_with_cheater(policy);
+            assert_legal_game(&game);
+            assert_eq!(game.choices.len(), 13);
+            for idx in 0..NUM_CARDS {
+                if game.whole_agent_sequence[idx] == 0 {
+                    assert!(!game.choices.contains(&game.whole_card_sequence[idx]));
+                }
+            }
+            assert_eq!(game.extra_penalty_points, vec![points, 0, 0, 0]);
+        }
+    }
+
+    #[test]
+    fn queen_of_spades_breaks_hearts_only_if_the_rules_say_so() {
+        assert!(!breaks_heart(S_Q, &rules_with(false, false, false)));
+        assert!(breaks_heart(S_Q, &rules_with(true, false, false)));
+        assert!(breaks_heart(card("H-2"), &rules_with(false, false, false)));
+    }
+}
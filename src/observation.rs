@@ -0,0 +1,190 @@
+//
+// Observation encoding
+//
+// The state of a game seen from the agent who is about to play a card is encoded as a flat vector
+// so that it can be fed to learning agents or exported as a dataset.
+// Only the information passed to `Agent::select_card` is used, so an agent can encode its own observation.
+//
+// Layout of the vector:
+//   [  0,  52) cards in the hand
+//   [ 52, 104) cards played in the previous tricks
+//   [104, 156) cards played in the current trick
+//   [156, 208) penalty points of each card under the rule set
+//   [208, 214) penalty points acquired so far by the agent itself and the following players in the order of play;
+//              the entries after the number of players are 0
+//   214        breaking heart has occurred
+//   215        trick / number of tricks in a game
+//   216        turn / number of players
+//   217        time for the move / move_time_ms of the rule set (1 if there is no limit per move)
+//   218        time left for the game / game_time_ms of the rule set (1 if there is no limit per game)
+// Penalty points are divided by the total penalty points of the rule set (26 by default).
+//
+
+use crate::clock::TimeBudget;
+use crate::rules::RuleSet;
+use crate::{determine_winner, get_penalty_point, is_valid_card, MAX_PLAYERS, NUM_CARDS};
+
+const HAND_OFFSET: usize = 0;
+const PLAYED_OFFSET: usize = NUM_CARDS;
+const TRICK_OFFSET: usize = NUM_CARDS * 2;
+const CARD_PENALTY_OFFSET: usize = NUM_CARDS * 3;
+const PENALTY_OFFSET: usize = NUM_CARDS * 4;
+const BH_FLAG_OFFSET: usize = PENALTY_OFFSET + MAX_PLAYERS;
+const TRICK_NO_OFFSET: usize = BH_FLAG_OFFSET + 1;
+const TURN_OFFSET: usize = TRICK_NO_OFFSET + 1;
+const MOVE_TIME_OFFSET: usize = TURN_OFFSET + 1;
+const GAME_TIME_OFFSET: usize = MOVE_TIME_OFFSET + 1;
+
+pub const OBS_SIZE: usize = GAME_TIME_OFFSET + 1;
+
+pub fn encode_observation(
+    hand: &[i32],
+    whole_card_sequence: &[i32; NUM_CARDS],
+    whole_agent_sequence: &[i32; NUM_CARDS],
+    trick: usize,
+    turn: usize,
+    bh_flag: bool,
+    rules: &RuleSet,
+    budget: TimeBudget,
+) -> [f32; OBS_SIZE] {
+    let mut obs: [f32; OBS_SIZE] = [0.0; OBS_SIZE];
+    let num_players = rules.num_players;
+    let total_penalty_points = rules.get_total_penalty_points().max(1) as f32;
+
+    for card in hand {
+        if *card != -1 {
+            obs[HAND_OFFSET + *card as usize] = 1.0;
+        }
+    }
+
+    for i in 0..(trick * num_players) {
+        obs[PLAYED_OFFSET + whole_card_sequence[i] as usize] = 1.0;
+    }
+
+    for i in 0..turn {
+        obs[TRICK_OFFSET + whole_card_sequence[trick * num_players + i] as usize] = 1.0;
+    }
+
+    // The penalty points of the cards are given so that agents can adapt to the scoring of the rule set.
+    for card in 0..NUM_CARDS {
+        obs[CARD_PENALTY_OFFSET + card] =
+            (get_penalty_point(card as i32, rules) as f32) / total_penalty_points;
+    }
+
+    // Penalty points are arranged relative to the agent itself, since the agent does not know its seat number.
+    let own_seat = get_own_seat(
+        whole_card_sequence,
+        whole_agent_sequence,
+        trick,
+        turn,
+        rules,
+    );
+    if own_seat != -1 {
+        let penalty_points =
+            calc_acquired_penalty_points(whole_card_sequence, whole_agent_sequence, trick, rules);
+        for i in 0..num_players {
+            let seat = (own_seat as usize + i) % num_players;
+            obs[PENALTY_OFFSET + i] = (penalty_points[seat] as f32) / total_penalty_points;
+        }
+    }
+
+    if bh_flag {
+        obs[BH_FLAG_OFFSET] = 1.0;
+    }
+    obs[TRICK_NO_OFFSET] = (trick as f32) / (rules.get_hand_size() as f32);
+    obs[TURN_OFFSET] = (turn as f32) / (num_players as f32);
+    obs[MOVE_TIME_OFFSET] = get_time_fraction(budget.move_limit, rules.move_time_ms);
+    obs[GAME_TIME_OFFSET] = get_time_fraction(budget.game_left, rules.game_time_ms);
+
+    return obs;
+}
+
+// The fraction of the time limit (in milliseconds, 0 for no limit) that is left.
+fn get_time_fraction(left: Option<std::time::Duration>, limit_ms: u64) -> f32 {
+    return match left {
+        Some(left) if limit_ms > 0 => {
+            ((left.as_secs_f64() * 1000.0 / limit_ms as f64) as f32).min(1.0)
+        }
+        _ => 1.0,
+    };
+}
+
+pub fn legal_mask(
+    hand: &[i32],
+    whole_card_sequence: &[i32; NUM_CARDS],
+    trick: usize,
+    bh_flag: bool,
+    rules: &RuleSet,
+) -> [bool; NUM_CARDS] {
+    let num_players = rules.num_players;
+    let card_sequence = &whole_card_sequence[(trick * num_players)..((trick + 1) * num_players)];
+
+    let mut mask: [bool; NUM_CARDS] = [false; NUM_CARDS];
+    for card in hand {
+        if *card != -1 && is_valid_card(hand, card_sequence, *card, trick, bh_flag, rules) {
+            mask[*card as usize] = true;
+        }
+    }
+    return mask;
+}
+
+// Getting the seat number of the agent who plays the next card.
+// It cannot be determined only at the very beginning of a game, where -1 is returned.
+fn get_own_seat(
+    whole_card_sequence: &[i32; NUM_CARDS],
+    whole_agent_sequence: &[i32; NUM_CARDS],
+    trick: usize,
+    turn: usize,
+    rules: &RuleSet,
+) -> i32 {
+    let num_players = rules.num_players;
+    if turn > 0 {
+        let previous_agent = whole_agent_sequence[trick * num_players + turn - 1];
+        return (previous_agent + 1) % (num_players as i32);
+    }
+    if trick > 0 {
+        let (card_sequence, agent_sequence) = get_trick_sequence(
+            whole_card_sequence,
+            whole_agent_sequence,
+            trick - 1,
+            num_players,
+        );
+        return determine_winner(agent_sequence, card_sequence);
+    }
+    return -1;
+}
+
+fn calc_acquired_penalty_points(
+    whole_card_sequence: &[i32; NUM_CARDS],
+    whole_agent_sequence: &[i32; NUM_CARDS],
+    trick: usize,
+    rules: &RuleSet,
+) -> Vec<i32> {
+    let mut penalty_points: Vec<i32> = vec![0; rules.num_players];
+    for t in 0..trick {
+        let (card_sequence, agent_sequence) = get_trick_sequence(
+            whole_card_sequence,
+            whole_agent_sequence,
+            t,
+            rules.num_players,
+        );
+        let winner = determine_winner(agent_sequence, card_sequence) as usize;
+        for card in card_sequence {
+            penalty_points[winner] += get_penalty_point(*card, rules);
+        }
+    }
+    return penalty_points;
+}
+
+fn get_trick_sequence<'a>(
+    whole_card_sequence: &'a [i32; NUM_CARDS],
+    whole_agent_sequence: &'a [i32; NUM_CARDS],
+    trick: usize,
+    num_players: usize,
+) -> (&'a [i32], &'a [i32]) {
+    let range = (trick * num_players)..((trick + 1) * num_players);
+    return (
+        &whole_card_sequence[range.clone()],
+        &whole_agent_sequence[range],
+    );
+}
@@ -0,0 +1,118 @@
+//
+// Game records
+//
+// A finished game is saved as a JSON file with the dealt cards (the same number of cards for each seat in order)
+// and the sequences of the played cards and agents (-1 after the last card), which are enough to replay the game,
+// together with the rule set under which the game was played.
+//
+
+use std::fs;
+use std::io::{self, Error};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::HeartsError;
+use crate::legality::GameState;
+use crate::rules::RuleSet;
+use crate::{MAX_PLAYERS, NUM_CARDS};
+
+#[derive(Serialize, Deserialize)]
+pub struct GameRecord {
+    pub dealt_cards: Vec<i32>,
+    pub card_sequence: Vec<i32>,
+    pub agent_sequence: Vec<i32>,
+    #[serde(default)]
+    pub rules: RuleSet,
+}
+
+impl GameRecord {
+    pub fn new(
+        dealt_cards: &[i32],
+        whole_card_sequence: &[i32; NUM_CARDS],
+        whole_agent_sequence: &[i32; NUM_CARDS],
+        rules: &RuleSet,
+    ) -> Self {
+        Self {
+            dealt_cards: dealt_cards.to_vec(),
+            card_sequence: whole_card_sequence.to_vec(),
+            agent_sequence: whole_agent_sequence.to_vec(),
+            rules: rules.clone(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = serde_json::to_string(self).map_err(Error::other)?;
+        return fs::write(path, text);
+    }
+
+    pub fn load(path: &Path) -> Result<Self, HeartsError> {
+        let text = fs::read_to_string(path)?;
+        let record: Self =
+            serde_json::from_str(&text).map_err(|e| HeartsError::InvalidRecord(e.to_string()))?;
+
+        let num_players = record.rules.num_players;
+        if !(3..=MAX_PLAYERS).contains(&num_players) {
+            return Err(HeartsError::InvalidRecord(
+                "invalid number of players".to_string(),
+            ));
+        }
+
+        // Every card in the deck must be dealt and played exactly once.
+        let mut dealt = record.dealt_cards.clone();
+        let mut played: Vec<i32> = record
+            .card_sequence
+            .iter()
+            .copied()
+            .filter(|c| *c != -1)
+            .collect();
+        dealt.sort();
+        played.sort();
+        let deck = record.rules.get_deck();
+        if record.card_sequence.len() != NUM_CARDS || dealt != deck || played != deck {
+            return Err(HeartsError::InvalidRecord(
+                "every card must be dealt and played exactly once".to_string(),
+            ));
+        }
+        if record.agent_sequence.len() != NUM_CARDS
+            || record.agent_sequence[..deck.len()]
+                .iter()
+                .any(|a| *a < 0 || *a >= num_players as i32)
+        {
+            return Err(HeartsError::InvalidRecord(
+                "invalid agent sequence".to_string(),
+            ));
+        }
+
+        // Every card must be played from the hand of the player in turn and be legal under the rule set,
+        // except for reneges if they are allowed.
+        let hand_size = record.rules.get_hand_size();
+        let hands: Vec<Vec<i32>> = record
+            .dealt_cards
+            .chunks(hand_size)
+            .map(|hand| hand.to_vec())
+            .collect();
+        let mut state = GameState::new(hands, &record.rules);
+        for idx in 0..deck.len() {
+            let seat = record.agent_sequence[idx] as usize;
+            let card = record.card_sequence[idx];
+            if seat != state.seat {
+                return Err(HeartsError::InvalidRecord(format!(
+                    "card {} is not played by the player in turn",
+                    idx + 1
+                )));
+            }
+            state.play(card, &record.rules)?;
+        }
+
+        return Ok(record);
+    }
+
+    pub fn whole_card_sequence(&self) -> [i32; NUM_CARDS] {
+        return self.card_sequence.clone().try_into().unwrap();
+    }
+
+    pub fn whole_agent_sequence(&self) -> [i32; NUM_CARDS] {
+        return self.agent_sequence.clone().try_into().unwrap();
+    }
+}
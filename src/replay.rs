@@ -0,0 +1,73 @@
+//
+// Replaying a finished game
+//
+// The cards dealt at the beginning of a game and the sequences of the played cards and agents
+// are enough to reconstruct what each agent observed when it played a card.
+//
+
+use crate::rules::RuleSet;
+use crate::{breaks_heart, NUM_CARDS};
+
+// The situation in which an agent played a card, with the arguments that were passed to `Agent::select_card`.
+// The sequences are filled only up to the card played at this point.
+pub struct DecisionPoint<'a> {
+    pub seat: usize,
+    pub hand: &'a [i32],
+    pub whole_card_sequence: &'a [i32; NUM_CARDS],
+    pub whole_agent_sequence: &'a [i32; NUM_CARDS],
+    pub trick: usize,
+    pub turn: usize,
+    pub bh_flag: bool,
+    pub card: i32,
+}
+
+pub fn replay_game<F: FnMut(&DecisionPoint)>(
+    dealt_cards: &[i32],
+    whole_card_sequence: &[i32; NUM_CARDS],
+    whole_agent_sequence: &[i32; NUM_CARDS],
+    rules: &RuleSet,
+    mut f: F,
+) {
+    let num_players = rules.num_players;
+    let hand_size = rules.get_hand_size();
+    let mut hands: Vec<Vec<i32>> = Vec::new();
+    for i in 0..num_players {
+        let mut hand = dealt_cards[(i * hand_size)..((i + 1) * hand_size)].to_vec();
+        hand.sort();
+        hands.push(hand);
+    }
+
+    // The sequences are filled in one by one, as they were during the game.
+    let mut card_sequence: [i32; NUM_CARDS] = [-1; NUM_CARDS];
+    let mut agent_sequence: [i32; NUM_CARDS] = [-1; NUM_CARDS];
+    let mut bh_flag = false;
+
+    for idx in 0..(num_players * hand_size) {
+        let seat = whole_agent_sequence[idx] as usize;
+        let card = whole_card_sequence[idx];
+
+        f(&DecisionPoint {
+            seat,
+            hand: &hands[seat],
+            whole_card_sequence: &card_sequence,
+            whole_agent_sequence: &agent_sequence,
+            trick: idx / num_players,
+            turn: idx % num_players,
+            bh_flag,
+            card,
+        });
+
+        for i in 0..hand_size {
+            if hands[seat][i] == card {
+                hands[seat][i] = -1;
+                break;
+            }
+        }
+        card_sequence[idx] = card;
+        agent_sequence[idx] = seat as i32;
+
+        if breaks_heart(card, rules) {
+            bh_flag = true;
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//
+// Rule variants
+//
+// The rules that differ among house variants are collected in RuleSet, which the engine consults everywhere.
+// The default rule set reproduces the rules this program has always played with:
+// four players, C-2 leads the first trick, hearts count 1 and S-Q counts 13, there is no passing, and so on.
+// A rule set can be loaded from a TOML or JSON file; missing entries take the default values.
+//
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    get_penalty_point, get_suit, load_config, CLUB, C_2, C_3, D_2, D_3, D_J, MAX_PLAYERS, NUM_CARDS,
+};
+
+// What happens when a player acquires all of the penalty points in a game ("shooting the moon").
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MoonShot {
+    // Nothing special happens.
+    Disabled,
+    // The other players receive all of the penalty points instead, and the shooter receives none.
+    AddToOthers,
+    // The shooter's penalty points are subtracted instead of added.
+    SubtractFromSelf,
+}
+
+// How many penalty points each heart counts.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeartScoring {
+    // Every heart counts heart_points.
+    Flat,
+    // Each heart counts its pip value (Spot Hearts): 2-10 as numbered, J 11, Q 12, K 13 and A 14.
+    Spot,
+    // As Spot, but J, Q and K count 10.
+    SpotFaceTen,
+}
+
+// To whom each player passes cards before a game.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Passing {
+    // To the next player (the player who plays after the passing player in a trick).
+    Left,
+    // To the previous player.
+    Right,
+    // To the opposite player.
+    Across,
+    // No cards are passed.
+    Hold,
+}
+
+// What the engine does when an agent plays a card that it does not have or that may not be played.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IllegalMovePolicy {
+    // The game is aborted with an error.
+    Abort,
+    // A random legal card is played instead.
+    Random,
+    // A random legal card is played instead, and the offender receives illegal_move_points.
+    Penalize,
+}
+
+// The card played instead of the agent's choice when the agent runs out of time.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeoutFallback {
+    // A random legal card.
+    Random,
+    // The legal card of the lowest rank.
+    Lowest,
+    // The choice of the rule-based agent with the hand-picked values.
+    RuleBased,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuleSet {
+    // Number of players from three to six; the deck is adjusted by removing low clubs and diamonds
+    // (D-2 for three players, C-2 and D-2 for five, and C-2, D-2, C-3 and D-3 for six).
+    pub num_players: usize,
+
+    // The card that must lead the first trick; its holder is the leading player.
+    // If it is removed from the deck, the lowest club in the deck is used instead.
+    // It may be led even if it is a heart or S-Q, whatever the rules on breaking hearts say.
+    #[serde(with = "card_name")]
+    pub opening_card: i32,
+
+    // Whether a player who cannot follow suit may play a heart or S-Q in the first trick.
+    // Even if it is false, a player who has only such cards may play them.
+    pub points_on_first_trick: bool,
+
+    // Whether playing S-Q breaks hearts, as playing a heart does.
+    // S-Q is then treated as a heart when leading, and it cannot be led until hearts are broken.
+    pub queen_breaks_hearts: bool,
+
+    // Whether a heart may be led even before hearts are broken (except in the first trick).
+    pub hearts_lead_anytime: bool,
+
+    // Whether a leading player who has only hearts and S-Q may lead S-Q before hearts are broken,
+    // when S-Q breaks hearts; otherwise the player must lead a heart.
+    pub queen_lead_with_hearts: bool,
+
+    // Penalty points of each heart and of S-Q.
+    pub heart_scoring: HeartScoring,
+    pub heart_points: i32,
+    pub queen_points: i32,
+
+    // Penalty points of S-K and S-A; 10 and 7 in the Black Maria variant.
+    pub king_points: i32,
+    pub ace_points: i32,
+
+    // Points of D-J; it is usually negative (a bonus), -10 in the Omnibus variant, and 0 disables the card.
+    pub jack_points: i32,
+
+    // Shooting the moon means taking all of the hearts and S-Q; D-J does not count, and its points are added
+    // to whoever took it. If it is true, the shooter must also take D-J.
+    pub moon_shot: MoonShot,
+    pub moon_requires_jack: bool,
+
+    // Passing directions, applied to the games in turn and repeated; no cards are passed if it is empty.
+    pub passing: Vec<Passing>,
+    pub num_passed_cards: usize,
+
+    // A match ends when a player's total penalty points reach this score; 0 means that games are not grouped
+    // into matches.
+    pub end_score: i32,
+
+    // Whether opposite seats are partners, whose penalty points are combined as a team;
+    // the number of players must be even. Matches are then played between the teams.
+    pub partnership: bool,
+
+    // How an illegal move by an agent is handled, and the penalty points of the offender for each illegal move.
+    pub illegal_move: IllegalMovePolicy,
+    pub illegal_move_points: i32,
+
+    // Whether a player may fail to follow suit (renege) as at a table, where the cards in the hands are hidden.
+    // Reneges are detected from the play history at the end of a game, and the offender receives renege_points
+    // for each of them.
+    pub allow_renege: bool,
+    pub renege_points: i32,
+
+    // Time each agent may think per move and in total per game, in milliseconds; 0 means no limit.
+    // When an agent runs out of time, the fallback card is played instead of its choice.
+    pub move_time_ms: u64,
+    pub game_time_ms: u64,
+    pub timeout_fallback: TimeoutFallback,
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        Self {
+            num_players: 4,
+            opening_card: C_2,
+            points_on_first_trick: true,
+            queen_breaks_hearts: false,
+            hearts_lead_anytime: false,
+            queen_lead_with_hearts: false,
+            heart_scoring: HeartScoring::Flat,
+            heart_points: 1,
+            queen_points: 13,
+            king_points: 0,
+            ace_points: 0,
+            jack_points: 0,
+            moon_shot: MoonShot::Disabled,
+            moon_requires_jack: false,
+            passing: Vec::new(),
+            num_passed_cards: 3,
+            end_score: 0,
+            partnership: false,
+            illegal_move: IllegalMovePolicy::Abort,
+            illegal_move_points: 26,
+            allow_renege: false,
+            renege_points: 26,
+            move_time_ms: 0,
+            game_time_ms: 0,
+            timeout_fallback: TimeoutFallback::Random,
+        }
+    }
+}
+
+impl RuleSet {
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let rules: Self = load_config(path)?;
+        rules.validate()?;
+        return Ok(rules);
+    }
+
+    // Checking that the rule set can be played.
+    pub fn validate(&self) -> std::io::Result<()> {
+        let rules = self;
+        if !(3..=MAX_PLAYERS).contains(&rules.num_players) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("num_players must be from 3 to {}", MAX_PLAYERS),
+            ));
+        }
+        if rules.partnership && !rules.num_players.is_multiple_of(2) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "partnership requires an even number of players",
+            ));
+        }
+        if rules.num_passed_cards >= rules.get_hand_size() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "num_passed_cards must be less than the number of cards in a hand",
+            ));
+        }
+        return Ok(());
+    }
+
+    // The cards removed from the deck so that it can be dealt evenly.
+    fn get_removed_cards(&self) -> Vec<i32> {
+        return match self.num_players {
+            3 => vec![D_2],
+            5 => vec![C_2, D_2],
+            6 => vec![C_2, D_2, C_3, D_3],
+            _ => Vec::new(),
+        };
+    }
+
+    // The cards used in a game.
+    pub fn get_deck(&self) -> Vec<i32> {
+        let removed_cards = self.get_removed_cards();
+        return (0..NUM_CARDS as i32)
+            .filter(|c| !removed_cards.contains(c))
+            .collect();
+    }
+
+    // Number of cards dealt to each player, which is also the number of tricks in a game.
+    pub fn get_hand_size(&self) -> usize {
+        return (NUM_CARDS - self.get_removed_cards().len()) / self.num_players;
+    }
+
+    // Total penalty points in a game, which a player must take to shoot the moon; D-J is not counted.
+    pub fn get_total_penalty_points(&self) -> i32 {
+        return self
+            .get_deck()
+            .iter()
+            .filter(|c| **c != D_J)
+            .map(|c| get_penalty_point(*c, self))
+            .sum();
+    }
+
+    // The seat of the partner (the opposite seat) in partnership mode.
+    pub fn get_partner(&self, seat: usize) -> Option<usize> {
+        if !self.partnership {
+            return None;
+        }
+        return Some((seat + self.num_players / 2) % self.num_players);
+    }
+
+    // Number of teams; each player is a team of its own unless in partnership mode.
+    pub fn get_num_teams(&self) -> usize {
+        if self.partnership {
+            return self.num_players / 2;
+        }
+        return self.num_players;
+    }
+
+    // Penalty points of each team; team t consists of seat t and its partner.
+    pub fn get_team_points(&self, penalty_points: &[i32]) -> Vec<i32> {
+        let mut team_points: Vec<i32> = vec![0; self.get_num_teams()];
+        for (seat, p) in penalty_points.iter().enumerate() {
+            team_points[seat % self.get_num_teams()] += p;
+        }
+        return team_points;
+    }
+
+    pub fn get_opening_card(&self) -> i32 {
+        let deck = self.get_deck();
+        if deck.contains(&self.opening_card) {
+            return self.opening_card;
+        }
+        return *deck.iter().find(|c| get_suit(**c) == CLUB).unwrap();
+    }
+
+    // The passing direction in the given game (counted from 1).
+    pub fn get_passing(&self, game: usize) -> Passing {
+        if self.passing.is_empty() || self.num_passed_cards == 0 {
+            return Passing::Hold;
+        }
+        return self.passing[(game - 1) % self.passing.len()];
+    }
+}
+
+// Cards are written by their names such as "C-2" in rule files.
+mod card_name {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    use crate::CARD_NAME;
+
+    pub fn serialize<S: Serializer>(card: &i32, serializer: S) -> Result<S::Ok, S::Error> {
+        return serializer.serialize_str(CARD_NAME[*card as usize]);
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i32, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        return match CARD_NAME.iter().position(|n| *n == name) {
+            Some(card) => Ok(card as i32),
+            None => Err(de::Error::custom(format!("unknown card {}", name))),
+        };
+    }
+}
@@ -0,0 +1,197 @@
+//
+// Self-play training of the linear Q-learning agent
+//
+// In each game, the learner sits at a rotating seat and the other seats are taken by
+// random agents, rule-based agents or copies of the learner with the current weights.
+// After the game, the learner's decisions are replayed and the weights are updated by Q-learning:
+//   Q(s, a) <- Q(s, a) + ALPHA * (r + max_a' Q(s', a') - Q(s, a))
+// where r is the negative number of penalty points (/ the total of the rule set) acquired in the trick
+// and s' is the situation in which the learner plays its next card.
+//
+
+use std::io;
+use std::path::Path;
+
+use rand::Rng;
+
+use crate::agents::linear_q_agent::{
+    calc_features, calc_value, get_penalty_scale, load_weights, save_weights, LinearQAgent,
+    NUM_FEATURES,
+};
+use crate::observation::legal_mask;
+use crate::replay::replay_game;
+use crate::rules::RuleSet;
+use crate::{
+    calc_penalty_points, determine_winner, get_penalty_point, play_one_game, Agent, RandomAgent,
+    RuleBasedAgent, NUM_CARDS,
+};
+
+// Learning rate
+const ALPHA: f32 = 0.01;
+
+// Probability that the learner plays a random card while training
+const EPSILON: f64 = 0.1;
+
+// Number of games between progress reports
+const REPORT_INTERVAL: usize = 1000;
+
+// A decision of the learner: the features of the played card and of all the valid cards.
+struct Step {
+    trick: usize,
+    features: [f32; NUM_FEATURES],
+    candidates: Vec<[f32; NUM_FEATURES]>,
+}
+
+pub fn train(rules: &RuleSet, num_games: usize, weights_path: &Path) -> io::Result<()> {
+    // Training is resumed from the saved weights if they exist.
+    let mut weights: [f32; NUM_FEATURES] = if weights_path.exists() {
+        load_weights(weights_path)?
+    } else {
+        [0.0; NUM_FEATURES]
+    };
+
+    let mut rng = rand::thread_rng();
+    let mut total_penalty_points = 0;
+
+    for game in 1..=num_games {
+        let learner_seat = game % rules.num_players;
+
+        let mut agents: Vec<Box<dyn Agent>> = Vec::new();
+        for i in 0..rules.num_players {
+            if i == learner_seat {
+                agents.push(Box::new(LinearQAgent::new(weights, EPSILON)));
+            } else {
+                match rng.gen_range(0..3) {
+                    0 => agents.push(Box::new(RandomAgent::new())),
+                    1 => agents.push(Box::new(RuleBasedAgent::new())),
+                    _ => agents.push(Box::new(LinearQAgent::new(weights, 0.0))),
+                }
+            }
+        }
+
+        let mut whole_card_sequence: [i32; NUM_CARDS] = [-1; NUM_CARDS];
+        let mut whole_agent_sequence: [i32; NUM_CARDS] = [-1; NUM_CARDS];
+        let mut extra_penalty_points: Vec<i32> = vec![0; rules.num_players];
+        let dealt_cards = play_one_game(
+            &mut agents,
+            rules,
+            game,
+            rand::random(),
+            &mut whole_card_sequence,
+            &mut whole_agent_sequence,
+            &mut extra_penalty_points,
+        )
+        .map_err(io::Error::other)?;
+
+        update_weights(
+            &mut weights,
+            learner_seat,
+            &dealt_cards,
+            &whole_card_sequence,
+            &whole_agent_sequence,
+            rules,
+        );
+
+        let penalty_points =
+            calc_penalty_points(&whole_card_sequence, &whole_agent_sequence, rules);
+        total_penalty_points += penalty_points[learner_seat] + extra_penalty_points[learner_seat];
+
+        if game % REPORT_INTERVAL == 0 || game == num_games {
+            let num_reported = (game - 1) % REPORT_INTERVAL + 1;
+            println!(
+                "game {}: averaged penalty points of the learner = {}",
+                game,
+                (total_penalty_points as f32) / (num_reported as f32)
+            );
+            total_penalty_points = 0;
+        }
+    }
+
+    save_weights(weights_path, &weights)?;
+    println!("The weights were saved to {}", weights_path.display());
+
+    return Ok(());
+}
+
+fn update_weights(
+    weights: &mut [f32; NUM_FEATURES],
+    learner_seat: usize,
+    dealt_cards: &[i32],
+    whole_card_sequence: &[i32; NUM_CARDS],
+    whole_agent_sequence: &[i32; NUM_CARDS],
+    rules: &RuleSet,
+) {
+    let mut steps: Vec<Step> = Vec::new();
+    replay_game(
+        dealt_cards,
+        whole_card_sequence,
+        whole_agent_sequence,
+        rules,
+        |d| {
+            if d.seat != learner_seat {
+                return;
+            }
+            let mask = legal_mask(d.hand, d.whole_card_sequence, d.trick, d.bh_flag, rules);
+            let mut candidates: Vec<[f32; NUM_FEATURES]> = Vec::new();
+            for card in 0..NUM_CARDS {
+                if mask[card] {
+                    candidates.push(calc_features(
+                        d.hand,
+                        d.whole_card_sequence,
+                        d.trick,
+                        d.turn,
+                        d.bh_flag,
+                        card as i32,
+                        rules,
+                    ));
+                }
+            }
+            steps.push(Step {
+                trick: d.trick,
+                features: calc_features(
+                    d.hand,
+                    d.whole_card_sequence,
+                    d.trick,
+                    d.turn,
+                    d.bh_flag,
+                    d.card,
+                    rules,
+                ),
+                candidates,
+            });
+        },
+    );
+
+    // Penalty points acquired by the learner in each trick
+    let num_players = rules.num_players;
+    let mut rewards: Vec<f32> = vec![0.0; rules.get_hand_size()];
+    for trick in 0..rewards.len() {
+        let card_sequence =
+            &whole_card_sequence[(trick * num_players)..((trick + 1) * num_players)];
+        let agent_sequence =
+            &whole_agent_sequence[(trick * num_players)..((trick + 1) * num_players)];
+        if determine_winner(agent_sequence, card_sequence) as usize == learner_seat {
+            let penalty: i32 = card_sequence
+                .iter()
+                .map(|c| get_penalty_point(*c, rules))
+                .sum();
+            rewards[trick] = -(penalty as f32) / get_penalty_scale(rules);
+        }
+    }
+
+    for i in 0..steps.len() {
+        let mut target = rewards[steps[i].trick];
+        if i + 1 < steps.len() {
+            target += steps[i + 1]
+                .candidates
+                .iter()
+                .map(|f| calc_value(weights, f))
+                .fold(f32::NEG_INFINITY, f32::max);
+        }
+
+        let error = target - calc_value(weights, &steps[i].features);
+        for j in 0..NUM_FEATURES {
+            weights[j] += ALPHA * error * steps[i].features[j];
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//
+// Evolutionary tuning of the rule-based agent
+//
+// The constants of the rule-based agent (RuleBasedParams) are treated as a parameter vector
+// and tuned by a genetic algorithm; each individual is evaluated by the averaged penalty points
+// it acquires against a fixed pool of opponents, and the lower is the better.
+// All of the individuals of a generation play the same deals (common random numbers), so that they are compared
+// on the same luck, and the best individual of the last generation is compared again with the hand-picked values
+// on a larger set of fresh deals before it is reported.
+// The parameters that have no effect under the rule set, such as those of D-J unless it has negative points,
+// are not mutated.
+//
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::rules::{Passing, RuleSet};
+use crate::{
+    calc_penalty_points, play_one_game, Agent, RandomAgent, RuleBasedAgent, RuleBasedParams,
+    NUM_CARDS, NUM_PARAMS,
+};
+
+const POPULATION_SIZE: usize = 16;
+
+// Number of the best individuals that survive to the next generation without change.
+const NUM_ELITES: usize = 2;
+
+// Number of individuals compared in a tournament selection.
+const TOURNAMENT_SIZE: usize = 3;
+
+// Probability that each parameter is mutated.
+const MUTATION_RATE: f64 = 0.3;
+
+// Bounds of the parameters, except for lead_late_trick which is bounded by the number of tricks.
+const PARAM_MIN: i32 = -1000;
+const PARAM_MAX: i32 = 1000;
+
+// Opponents of the individuals:
+// 1 -> Random agent, 2 -> Rule-based agent with the hand-picked values.
+// In each game, the opponents at the other seats are taken from the pool cyclically.
+const OPPONENT_POOL: [i32; 4] = [2, 2, 2, 1];
+
+// The final comparison plays this many times the number of games per evaluation.
+const VALIDATION_FACTOR: usize = 10;
+
+pub fn tune(rules: &RuleSet, num_generations: usize, num_games: usize) -> RuleBasedParams {
+    let mut rng = rand::thread_rng();
+
+    let default_params = RuleBasedParams::default();
+
+    // The initial population consists of the hand-picked values and their mutants.
+    let mut population: Vec<[i32; NUM_PARAMS]> = vec![default_params.to_vec()];
+    while population.len() < POPULATION_SIZE {
+        population.push(mutate(&default_params.to_vec(), rules, &mut rng));
+    }
+
+    let mut candidate = default_params.to_vec();
+
+    for generation in 1..=num_generations {
+        let seeds = new_seeds(num_games, &mut rng);
+        let mut evaluated: Vec<([i32; NUM_PARAMS], f32)> = population
+            .iter()
+            .map(|v| (*v, evaluate(&RuleBasedParams::from_vec(v), rules, &seeds)))
+            .collect();
+        evaluated.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        candidate = evaluated[0].0;
+        println!(
+            "generation {}: best = {}, mean = {}, hand-picked values = {}",
+            generation,
+            evaluated[0].1,
+            evaluated.iter().map(|e| e.1).sum::<f32>() / (evaluated.len() as f32),
+            evaluate(&default_params, rules, &seeds)
+        );
+
+        let mut next: Vec<[i32; NUM_PARAMS]> =
+            evaluated.iter().take(NUM_ELITES).map(|e| e.0).collect();
+        while next.len() < POPULATION_SIZE {
+            let a = select(&evaluated, &mut rng);
+            let b = select(&evaluated, &mut rng);
+            next.push(mutate(&crossover(&a, &b, &mut rng), rules, &mut rng));
+        }
+        population = next;
+    }
+
+    // The score of the best individual in the last generation is biased by the selection,
+    // so it is compared with the hand-picked values on fresh deals.
+    let seeds = new_seeds(num_games * VALIDATION_FACTOR, &mut rng);
+    let candidate_params = RuleBasedParams::from_vec(&candidate);
+    let candidate_fitness = evaluate(&candidate_params, rules, &seeds);
+    let default_fitness = evaluate(&default_params, rules, &seeds);
+    println!(
+        "averaged penalty points in {} fresh games: {} (hand-picked values: {})",
+        seeds.len(),
+        candidate_fitness,
+        default_fitness
+    );
+    if candidate_fitness >= default_fitness {
+        println!("The hand-picked values remain the best.");
+        return default_params;
+    }
+
+    println!("best parameters: {:?}", candidate_params);
+    println!("best vector: {:?}", candidate);
+    println!(
+        "best profile:\n{}",
+        toml::to_string(&candidate_params).unwrap()
+    );
+
+    return candidate_params;
+}
+
+fn new_seeds<R: Rng>(num_games: usize, rng: &mut R) -> Vec<u64> {
+    return (0..num_games).map(|_| rng.gen()).collect();
+}
+
+// Averaged penalty points acquired by a rule-based agent with the given parameters in the games dealt from the seeds.
+fn evaluate(params: &RuleBasedParams, rules: &RuleSet, seeds: &[u64]) -> f32 {
+    let mut total_penalty_points = 0;
+
+    for (game, seed) in seeds.iter().enumerate() {
+        let seat = game % rules.num_players;
+
+        let mut agents: Vec<Box<dyn Agent>> = Vec::new();
+        let mut k = 0;
+        for i in 0..rules.num_players {
+            if i == seat {
+                agents.push(Box::new(RuleBasedAgent::with_params(*params)));
+            } else {
+                match OPPONENT_POOL[(game + k) % OPPONENT_POOL.len()] {
+                    1 => agents.push(Box::new(RandomAgent::new())),
+                    _ => agents.push(Box::new(RuleBasedAgent::new())),
+                }
+                k += 1;
+            }
+        }
+
+        let mut whole_card_sequence: [i32; NUM_CARDS] = [-1; NUM_CARDS];
+        let mut whole_agent_sequence: [i32; NUM_CARDS] = [-1; NUM_CARDS];
+        let mut extra_penalty_points: Vec<i32> = vec![0; rules.num_players];
+        play_one_game(
+            &mut agents,
+            rules,
+            game + 1,
+            *seed,
+            &mut whole_card_sequence,
+            &mut whole_agent_sequence,
+            &mut extra_penalty_points,
+        )
+        .expect("the rule-based and random agents play only legal cards");
+
+        let penalty_points =
+            calc_penalty_points(&whole_card_sequence, &whole_agent_sequence, rules);
+        total_penalty_points += penalty_points[seat] + extra_penalty_points[seat];
+    }
+
+    return (total_penalty_points as f32) / (seeds.len().max(1) as f32);
+}
+
+fn select<R: Rng>(evaluated: &[([i32; NUM_PARAMS], f32)], rng: &mut R) -> [i32; NUM_PARAMS] {
+    let winner = evaluated
+        .choose_multiple(rng, TOURNAMENT_SIZE)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap();
+    return winner.0;
+}
+
+fn crossover<R: Rng>(
+    a: &[i32; NUM_PARAMS],
+    b: &[i32; NUM_PARAMS],
+    rng: &mut R,
+) -> [i32; NUM_PARAMS] {
+    let mut child: [i32; NUM_PARAMS] = *a;
+    for i in 0..NUM_PARAMS {
+        if rng.gen_bool(0.5) {
+            child[i] = b[i];
+        }
+    }
+    return child;
+}
+
+fn mutate<R: Rng>(v: &[i32; NUM_PARAMS], rules: &RuleSet, rng: &mut R) -> [i32; NUM_PARAMS] {
+    let mut mutant: [i32; NUM_PARAMS] = *v;
+    for i in 0..NUM_PARAMS {
+        if rng.gen_bool(MUTATION_RATE) {
+            // The step is proportional to the magnitude of the parameter.
+            let step = (mutant[i].abs() / 5).max(1);
+            mutant[i] += rng.gen_range(-step..=step);
+        }
+    }
+
+    let mut params = RuleBasedParams::from_vec(&mutant);
+    freeze_inactive_params(&mut params, &RuleBasedParams::from_vec(v), rules);
+    params.lead_late_trick = params
+        .lead_late_trick
+        .clamp(0, rules.get_hand_size() as i32);
+    let mut mutant = params.to_vec();
+    for p in mutant.iter_mut() {
+        *p = (*p).clamp(PARAM_MIN, PARAM_MAX);
+    }
+    return mutant;
+}
+
+// Restoring the parameters that have no effect under the rules, so that they do not drift at random
+// and the reported profile keeps their values.
+fn freeze_inactive_params(
+    params: &mut RuleBasedParams,
+    original: &RuleBasedParams,
+    rules: &RuleSet,
+) {
+    if rules.jack_points >= 0 {
+        params.jack_capture_bonus = original.jack_capture_bonus;
+        params.jack_feed_penalty = original.jack_feed_penalty;
+    }
+    if rules.num_passed_cards == 0 || rules.passing.iter().all(|p| *p == Passing::Hold) {
+        params.pass_high_spade_base = original.pass_high_spade_base;
+        params.pass_heart_base = original.pass_heart_base;
+    }
+    if rules.king_points <= 0 && rules.ace_points <= 0 {
+        params.lead_penalty_spade_score = original.lead_penalty_spade_score;
+        params.follow_penalty_spade_keep_score = original.follow_penalty_spade_keep_score;
+    }
+    if !rules.partnership {
+        params.follow_partner_winning_score = original.follow_partner_winning_score;
+    }
+}